@@ -0,0 +1,128 @@
+use crate::{Groups, Project};
+use clap::ValueEnum;
+use std::collections::BTreeSet;
+
+/// The formats the dependency graph can be rendered in.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Format {
+    /// Graphviz
+    #[default]
+    Dot,
+
+    /// Mermaid, e.g. to embed in markdown
+    Mermaid,
+}
+
+/// An edge from a group to one of the commands (or groups) it runs.
+struct Edge<'a> {
+    from: &'a str,
+    to: &'a str,
+
+    /// Whether the edge is part of a cycle, which makes the group impossible to run.
+    cyclic: bool,
+}
+
+/// Render the commands and groups of a project as a dependency graph. Groups point to the commands
+/// they run, edges that are part of a cycle are highlighted.
+pub fn render(project: &Project, groups: &Groups, format: Format) -> String {
+    let mut nodes: BTreeSet<&str> = project.keys().map(String::as_str).collect();
+    nodes.extend(groups.keys().map(String::as_str));
+    nodes.extend(groups.values().flatten().map(String::as_str));
+
+    let edges: Vec<Edge> = groups
+        .iter()
+        .flat_map(|(from, members)| {
+            members.iter().map(move |to| Edge {
+                from,
+                to,
+                cyclic: reaches(groups, to, from, &mut BTreeSet::new()),
+            })
+        })
+        .collect();
+
+    match format {
+        Format::Dot => dot(&nodes, groups, &edges),
+        Format::Mermaid => mermaid(&nodes, groups, &edges),
+    }
+}
+
+/// Whether `target` can be reached by following the groups starting at `from`.
+fn reaches<'a>(
+    groups: &'a Groups,
+    from: &'a str,
+    target: &str,
+    seen: &mut BTreeSet<&'a str>,
+) -> bool {
+    if from == target {
+        return true;
+    }
+
+    if !seen.insert(from) {
+        return false;
+    }
+
+    groups
+        .get(from)
+        .into_iter()
+        .flatten()
+        .any(|member| reaches(groups, member, target, seen))
+}
+
+fn dot(nodes: &BTreeSet<&str>, groups: &Groups, edges: &[Edge]) -> String {
+    let mut out = vec!["digraph taco {".to_string()];
+
+    for node in nodes {
+        let shape = match groups.contains_key(*node) {
+            true => "ellipse",
+            false => "box",
+        };
+        out.push(format!("  {} [shape={}];", dot_id(node), shape));
+    }
+
+    for edge in edges {
+        let style = match edge.cyclic {
+            true => " [color=red]",
+            false => "",
+        };
+        out.push(format!(
+            "  {} -> {}{};",
+            dot_id(edge.from),
+            dot_id(edge.to),
+            style
+        ));
+    }
+
+    out.push("}".to_string());
+    out.join("\n")
+}
+
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn mermaid(nodes: &BTreeSet<&str>, groups: &Groups, edges: &[Edge]) -> String {
+    let mut out = vec!["graph TD".to_string()];
+
+    // Mermaid ids can't contain most special characters, so refer to the nodes by index instead.
+    let id = |name: &str| format!("n{}", nodes.iter().position(|node| *node == name).unwrap());
+
+    for node in nodes {
+        let label = node.replace('"', "#quot;");
+        out.push(match groups.contains_key(*node) {
+            true => format!("  {}([\"{}\"])", id(node), label),
+            false => format!("  {}[\"{}\"]", id(node), label),
+        });
+    }
+
+    for edge in edges {
+        out.push(format!("  {} --> {}", id(edge.from), id(edge.to)));
+    }
+
+    for (index, edge) in edges.iter().enumerate() {
+        if edge.cyclic {
+            out.push(format!("  linkStyle {} stroke:red", index));
+        }
+    }
+
+    out.join("\n")
+}
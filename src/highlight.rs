@@ -0,0 +1,94 @@
+use crate::shell::is_assignment;
+use colored::*;
+
+/// Highlight a shell command for display purposes. This is not a full shell parser, it only
+/// recognizes enough of the syntax (command names, flags, strings, variables and operators) to
+/// make long commands easier to scan.
+pub fn highlight(command: &str) -> String {
+    let mut out = String::new();
+    let mut chars = command.chars().peekable();
+
+    // Whether the next word is in command position, e.g. at the start or right after a pipe.
+    let mut expect_command = true;
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            // Whitespace
+            c if c.is_whitespace() => {
+                out.push(c);
+                chars.next();
+            }
+
+            // Strings
+            '\'' | '"' => {
+                let mut string = String::new();
+                string.push(c);
+                chars.next();
+
+                while let Some(next) = chars.next() {
+                    string.push(next);
+
+                    if next == '\\' && c == '"' {
+                        if let Some(escaped) = chars.next() {
+                            string.push(escaped);
+                        }
+                    } else if next == c {
+                        break;
+                    }
+                }
+
+                out.push_str(&string.green().to_string());
+                expect_command = false;
+            }
+
+            // Operators
+            c if is_operator(c) => {
+                let mut operator = String::new();
+                while let Some(&next) = chars.peek() {
+                    if !is_operator(next) {
+                        break;
+                    }
+                    operator.push(next);
+                    chars.next();
+                }
+
+                // Redirections are followed by a file name instead of a command.
+                expect_command = !operator.contains(['<', '>']);
+                out.push_str(&operator.magenta().to_string());
+            }
+
+            // Words
+            _ => {
+                let mut word = String::new();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || is_operator(next) || next == '\'' || next == '"' {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+
+                let highlighted = if expect_command && is_assignment(&word) {
+                    word.yellow()
+                } else if expect_command {
+                    expect_command = false;
+                    word.blue().bold()
+                } else if word.starts_with('-') {
+                    word.cyan()
+                } else if word.starts_with('$') {
+                    word.yellow()
+                } else {
+                    word.normal()
+                };
+
+                out.push_str(&highlighted.to_string());
+            }
+        }
+    }
+
+    out
+}
+
+fn is_operator(c: char) -> bool {
+    matches!(c, '|' | '&' | ';' | '<' | '>')
+}
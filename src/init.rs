@@ -0,0 +1,195 @@
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Result};
+
+/// The shells that `taco init` can generate an integration for.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Which pieces of the shell integration to include.
+pub struct Integration<'a> {
+    /// Complete the available commands after `taco`.
+    pub completions: bool,
+
+    /// List the available commands when changing into a directory that has any.
+    pub cd_hook: bool,
+
+    /// Commands that can be invoked without the `taco` prefix.
+    pub shims: &'a [String],
+}
+
+/// Generate the script that sets up the shell integration, to be evaluated in the rc file of the
+/// shell, e.g. `eval "$(taco init zsh)"`.
+pub fn script(shell: Shell, integration: &Integration) -> Result<String> {
+    let mut out = vec![commands_function(shell).to_string()];
+
+    if integration.completions {
+        out.push(completions(shell).to_string());
+    }
+
+    if integration.cd_hook {
+        out.push(cd_hook(shell).to_string());
+    }
+
+    for name in integration.shims {
+        out.push(shim(shell, name)?);
+    }
+
+    Ok(out.join("\n"))
+}
+
+/// A function that lists the names of the commands available in the current directory, one per
+/// line.
+fn commands_function(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            r#"__taco_commands() {
+  command taco complete-aliases 2>/dev/null
+}
+"#
+        }
+        Shell::Fish => {
+            r#"function __taco_commands
+    command taco complete-aliases 2>/dev/null
+end
+"#
+        }
+    }
+}
+
+fn completions(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => {
+            r#"__taco_complete() {
+  if [ "$COMP_CWORD" -eq 1 ]; then
+    COMPREPLY=($(compgen -W "$(__taco_commands)" -- "${COMP_WORDS[1]}"))
+  fi
+}
+complete -F __taco_complete taco
+"#
+        }
+        Shell::Zsh => {
+            r#"__taco_complete() {
+  if (( CURRENT == 2 )); then
+    compadd -- ${(f)"$(__taco_commands)"}
+  fi
+}
+compdef __taco_complete taco
+"#
+        }
+        Shell::Fish => {
+            r#"complete -c taco -f -n 'test (count (commandline -opc)) -eq 1' -a '(__taco_commands)'
+"#
+        }
+    }
+}
+
+fn cd_hook(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => {
+            r#"__taco_hook() {
+  if [ "$PWD" != "$__taco_pwd" ]; then
+    __taco_pwd="$PWD"
+    [ -n "$(__taco_commands)" ] && command taco ls
+  fi
+}
+PROMPT_COMMAND="__taco_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+"#
+        }
+        Shell::Zsh => {
+            r#"__taco_hook() {
+  [ -n "$(__taco_commands)" ] && command taco ls
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook chpwd __taco_hook
+"#
+        }
+        Shell::Fish => {
+            r#"function __taco_hook --on-variable PWD
+    if __taco_commands | string length -q
+        command taco ls
+    end
+end
+"#
+        }
+    }
+}
+
+fn shim(shell: Shell, name: &str) -> Result<String> {
+    // The name ends up as a function name in the script, so only allow names that are safe to use
+    // without quoting.
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(eyre!("\"{}\" can't be used as the name of a shim.", name));
+    }
+
+    Ok(match shell {
+        Shell::Bash | Shell::Zsh => format!("{0}() {{\n  command taco {0} \"$@\"\n}}\n", name),
+        Shell::Fish => format!("function {0}\n    command taco {0} $argv\nend\n", name),
+    })
+}
+
+/// Complete the commands of the current directory on top of the static completions that
+/// `taco completions` generates, it relies on the `_taco` function of the generated script.
+pub fn alias_completions(shell: clap_complete::Shell) -> Option<&'static str> {
+    match shell {
+        clap_complete::Shell::Bash => Some(
+            r#"
+__taco_complete_aliases() {
+  _taco "$@"
+  if [ "$COMP_CWORD" -eq 1 ]; then
+    COMPREPLY+=($(command taco complete-aliases "${COMP_WORDS[1]}" 2>/dev/null))
+  fi
+}
+complete -F __taco_complete_aliases -o bashdefault -o default taco
+"#,
+        ),
+        clap_complete::Shell::Zsh => Some(
+            r#"
+__taco_aliases() {
+  if (( CURRENT == 2 )); then
+    compadd -- ${(f)"$(command taco complete-aliases "$words[2]" 2>/dev/null)"}
+  fi
+}
+__taco_complete_aliases() {
+  _taco "$@"
+  __taco_aliases
+}
+compdef __taco_complete_aliases taco
+
+# When this file is autoloaded from the fpath, its body is the first completion. Later completions
+# go through `__taco_complete_aliases` instead.
+if [ "$funcstack[1]" = "_taco" ]; then
+  __taco_aliases
+fi
+"#,
+        ),
+        clap_complete::Shell::Fish => Some(
+            r#"
+complete -c taco -f -n '__fish_use_subcommand' -a '(command taco complete-aliases (commandline -ct) 2>/dev/null)'
+"#,
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zsh_completes_the_commands_after_every_completion() {
+        let script = alias_completions(clap_complete::Shell::Zsh).unwrap();
+
+        // `_taco` is replaced by the function it defines once the file is autoloaded, so the
+        // commands can't be completed from its body alone.
+        assert!(script.contains("__taco_complete_aliases() {\n  _taco \"$@\"\n  __taco_aliases\n}"));
+        assert!(script.contains("compdef __taco_complete_aliases taco"));
+    }
+}
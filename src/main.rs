@@ -1,27 +1,74 @@
-use clap::{Parser, Subcommand};
-use color_eyre::eyre::{eyre, Result};
+use clap::builder::FalseyValueParser;
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use color_eyre::eyre::{eyre, Result, WrapErr};
 use colored::*;
+use dialoguer::{Editor, MultiSelect};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use serde_json::value::RawValue;
+use spec::{CommandOptions, CommandSpec, CwdStrategy};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
 use std::fs;
 use std::fs::File;
-use std::io::{Error, Write};
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::io::{Error, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use template::TemplateEngine;
 
-type Project = BTreeMap<String, String>;
+mod graph;
+mod highlight;
+mod init;
+mod processes;
+mod progress;
+mod record;
+mod script;
+mod shell;
+mod spec;
+mod suggest;
+mod tee;
+mod template;
+mod trace;
+
+type Project = BTreeMap<String, CommandSpec>;
+type Groups = BTreeMap<String, Vec<String>>;
 
 /// Normalize all your commands by wrapping them in a taco
 #[derive(Parser, Debug)]
 #[clap(about, version, author)]
 struct Cli {
-    /// The current working directory
+    /// The current working directory, relative paths are resolved against the directory taco is
+    /// executed in
     #[clap(long, default_value = ".", global = true)]
     pwd: String,
 
-    /// Print the current command instead of executing it
-    #[clap(short, long)]
-    print: bool,
+    /// The config file to use instead of the one in your home directory, takes precedence over
+    /// TACO_CONFIG
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Append every process taco spawns to this file as a line of JSON, to debug what taco runs
+    #[clap(long, global = true, value_name = "FILE", env = "TACO_TRACE")]
+    trace_exec: Option<PathBuf>,
+
+    /// Use the taco.json next to the executable (or in the current directory) instead of the one
+    /// in your home directory
+    #[clap(long, global = true)]
+    portable: bool,
+
+    /// Don't color the output. Colors are also disabled when NO_COLOR is set, or when the output
+    /// isn't a terminal
+    #[clap(long, global = true, env = "TACO_NO_COLOR", value_parser = FalseyValueParser::new())]
+    no_color: bool,
+
+    /// Don't ask for confirmation, e.g. before running dangerous commands or overriding existing
+    /// commands
+    #[clap(short, long, global = true)]
+    yes: bool,
+
+    #[clap(flatten)]
+    exec: ExecArgs,
 
     /// The alias to execute
     alias: Option<String>,
@@ -34,6 +81,103 @@ struct Cli {
     command: Option<Commands>,
 }
 
+/// The flags that control how a command is executed. They can be passed to taco itself, as well as
+/// after `taco run {alias}`.
+#[derive(Args, Debug)]
+struct ExecArgs {
+    /// Resolve the commands as if taco was running in this directory, while still running them in
+    /// the current directory (unless their cwd_strategy says otherwise)
+    #[clap(long = "as", value_name = "PATH")]
+    resolve_as: Option<String>,
+
+    /// Print the current command instead of executing it
+    #[clap(short, long)]
+    print: bool,
+
+    /// Print every command instead of executing it, e.g. for demos or audits
+    #[clap(long, env = "TACO_NO_EXEC", value_parser = FalseyValueParser::new())]
+    no_exec: bool,
+
+    /// Prefix the printed command with a `cd` to the directory it would run in
+    #[clap(long, requires = "print")]
+    with_cd: bool,
+
+    /// Print the full shell invocation, including the passthrough arguments
+    #[clap(long, requires = "print")]
+    full: bool,
+
+    /// Run the command detached from the terminal, its output is written to a log file
+    #[clap(short, long, conflicts_with = "print")]
+    background: bool,
+
+    /// Write the output of the command to a file, while still showing it in the terminal
+    #[clap(short, long, conflicts_with_all = ["print", "background"])]
+    output: Option<PathBuf>,
+
+    /// Only write the stdout of the command to the output file
+    #[clap(long, requires = "output", conflicts_with = "output_stderr")]
+    output_stdout: bool,
+
+    /// Only write the stderr of the command to the output file
+    #[clap(long, requires = "output")]
+    output_stderr: bool,
+
+    /// Run commands that require a clean git working tree anyway
+    #[clap(long)]
+    allow_dirty: bool,
+
+    /// Run commands that only run once again, even when they already ran
+    #[clap(long)]
+    force: bool,
+
+    /// Reject passthrough arguments for commands that don't use them in their template
+    #[clap(long)]
+    strict_args: bool,
+
+    /// Ring the terminal bell when the command finished
+    #[clap(long)]
+    bell: bool,
+
+    /// Show how long a command has been running while it doesn't write any output
+    #[clap(long)]
+    progress: bool,
+
+    /// Print the result of the command as JSON to stderr once it finished
+    #[clap(long, conflicts_with_all = ["print", "background"])]
+    json_result: bool,
+
+    /// Show the raw output of commands that have a filter
+    #[clap(long)]
+    no_filter: bool,
+}
+
+impl ExecArgs {
+    /// Combine the flags that were passed before and after `run`, the latter win.
+    fn or(&self, before: &ExecArgs) -> ExecArgs {
+        ExecArgs {
+            resolve_as: self
+                .resolve_as
+                .clone()
+                .or_else(|| before.resolve_as.clone()),
+            print: self.print || before.print,
+            no_exec: self.no_exec || before.no_exec,
+            with_cd: self.with_cd || before.with_cd,
+            full: self.full || before.full,
+            background: self.background || before.background,
+            output: self.output.clone().or_else(|| before.output.clone()),
+            output_stdout: self.output_stdout || before.output_stdout,
+            output_stderr: self.output_stderr || before.output_stderr,
+            allow_dirty: self.allow_dirty || before.allow_dirty,
+            force: self.force || before.force,
+            strict_args: self.strict_args || before.strict_args,
+            bell: self.bell || before.bell,
+            progress: self.progress || before.progress,
+            json_result: self.json_result || before.json_result,
+            no_filter: self.no_filter || before.no_filter,
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Add a new command
@@ -43,30 +187,332 @@ enum Commands {
 
         /// The actual command to run
         arguments: Vec<String>,
+
+        /// Append to the existing command instead of replacing it
+        #[clap(long, allow_hyphen_values = true, conflicts_with_all = ["arguments", "prepend"])]
+        append: Option<String>,
+
+        /// Prepend to the existing command instead of replacing it
+        #[clap(long, allow_hyphen_values = true, conflicts_with = "arguments")]
+        prepend: Option<String>,
+
+        /// Overwrite an existing command without asking for confirmation
+        #[clap(short, long)]
+        force: bool,
+
+        /// Never overwrite an existing command, and fail instead
+        #[clap(long, conflicts_with = "force")]
+        no_overwrite: bool,
+
+        /// Environment variables to store with the command, with their current values
+        #[clap(long, value_delimiter = ',', conflicts_with_all = ["append", "prepend"])]
+        capture_env: Vec<String>,
+    },
+
+    /// Copy a command to a new name in the current project
+    Clone {
+        /// The name of the command to copy
+        name: String,
+
+        /// The name of the copy
+        new_name: String,
+    },
+
+    /// Rename a command of the current project
+    Rename {
+        /// The current name of the command
+        from: String,
+
+        /// The new name of the command
+        to: String,
     },
 
     /// Alias the current project to a predefined project
     Alias {
         /// The name of the alias
+        #[clap(required_unless_present_any = ["to", "rename"])]
+        name: Option<String>,
+
+        /// Inherit the commands of the project in another directory instead
+        #[clap(long, conflicts_with = "name")]
+        to: Option<String>,
+
+        /// Rename a predefined project, and update every alias that refers to it
+        #[clap(long, num_args = 2, value_names = ["OLD", "NEW"], conflicts_with_all = ["name", "to"])]
+        rename: Option<Vec<String>>,
+
+        /// Preview the commands the alias would add, without changing the config
+        #[clap(long, conflicts_with = "rename")]
+        dry_run: bool,
+    },
+
+    /// Remove an alias from the current project
+    Unalias {
+        /// The name of the alias, or the directory of the project (see `alias --to`)
+        name: String,
+    },
+
+    /// Run a command, even when its name is the same as one of taco's subcommands, e.g. `add`
+    Run {
+        #[clap(flatten)]
+        exec: ExecArgs,
+
+        /// The name of the command or group to run
+        alias: String,
+
+        /// The arguments to pass to the command
+        arguments: Vec<String>,
+    },
+
+    /// Run a command in a shell, and save it when you're happy with the result
+    Record {
+        /// The name of the alias for the command
+        name: String,
+    },
+
+    /// Add a group of commands that run in sequence
+    Group {
+        /// The name of the group
         name: String,
+
+        /// The names of the commands (or other groups) to run
+        #[clap(required = true)]
+        commands: Vec<String>,
     },
 
     /// Remove an existing command
     #[clap(name = "rm")]
     Remove {
         /// The name of the alias to remove
+        #[clap(required_unless_present = "interactive")]
+        name: Option<String>,
+
+        /// Select the commands to remove from a list
+        #[clap(short, long, conflicts_with = "name")]
+        interactive: bool,
+    },
+
+    /// Print the commands of a single project as a portable JSON snippet, or as a shell script
+    ExportProject {
+        /// The directory of the project, defaults to the current directory
+        path: Option<String>,
+
+        /// Include the aliases of the project, only for the JSON format
+        #[clap(long)]
+        with_aliases: bool,
+
+        /// The format to export the project in
+        #[clap(long, value_enum, default_value_t)]
+        format: ExportFormat,
+    },
+
+    /// Print the whole config, e.g. to back it up or to move it to another machine
+    Export {
+        /// Print the config as JSON, the default
+        #[clap(long, conflicts_with = "toml")]
+        json: bool,
+
+        /// Print the config as TOML
+        #[clap(long)]
+        toml: bool,
+    },
+
+    /// Merge the config of another machine or a teammate into this one, e.g. from `taco export`
+    Import {
+        /// The config file to import, reads from stdin when it's omitted
+        file: Option<String>,
+
+        /// Replace conflicting commands without asking
+        #[clap(long)]
+        overwrite: bool,
+    },
+
+    /// List the commands that were started in the background
+    Ps,
+
+    /// Stop a command that was started in the background
+    Stop {
+        /// The alias or PID of the process to stop
+        target: String,
+    },
+
+    /// Show which project defines the command that runs
+    Which {
+        /// The name of the command
         name: String,
+
+        /// Print the result in JSON format
+        #[clap(short, long)]
+        json: bool,
+    },
+
+    /// Check whether the executables that commands rely on are installed
+    CheckCommand {
+        /// The command to check
+        #[clap(required_unless_present = "all")]
+        name: Option<String>,
+
+        /// Check all the commands that are available in the current directory
+        #[clap(long, conflicts_with = "name")]
+        all: bool,
+    },
+
+    /// Check the shell syntax of commands without running them
+    Lint {
+        /// The command to check
+        #[clap(required_unless_present = "all")]
+        name: Option<String>,
+
+        /// Check all the commands that are available in the current directory
+        #[clap(long, conflicts_with = "name")]
+        all: bool,
+    },
+
+    /// Run the taco invocations in a file, one per line, in sequence
+    Batch {
+        /// The file with the invocations, e.g. `add test -- npm run test`
+        file: PathBuf,
+
+        /// Don't change the config at all when any of the invocations fails
+        #[clap(long)]
+        transaction: bool,
+    },
+
+    /// Print the groups and the commands they run as a dependency graph
+    Graph {
+        /// The format of the graph
+        #[clap(long, value_enum, default_value_t)]
+        format: graph::Format,
+    },
+
+    /// Print the completions of the subcommands and flags, e.g. `taco completions zsh > _taco`
+    Completions {
+        /// The shell to generate the completions for
+        #[clap(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Print the shell integration, add `eval "$(taco init zsh)"` to your shell's rc file
+    Init {
+        /// The shell to generate the integration for
+        #[clap(value_enum)]
+        shell: init::Shell,
+
+        /// Don't complete the available commands
+        #[clap(long)]
+        no_completions: bool,
+
+        /// List the available commands when changing into a directory that has any
+        #[clap(long)]
+        cd_hook: bool,
+
+        /// Commands that can be invoked without the `taco` prefix
+        #[clap(long, value_delimiter = ',')]
+        shims: Vec<String>,
+    },
+
+    /// Edit the config in your $EDITOR, it is only saved when it is valid
+    Edit {
+        /// Only edit the commands of the current project
+        #[clap(long)]
+        project: bool,
+    },
+
+    /// Remove the config, logs and other files taco wrote, but not taco itself
+    Uninstall,
+
+    /// List every project that has commands or aliases
+    List {
+        /// Print the projects in JSON format
+        #[clap(short, long)]
+        json: bool,
     },
 
     /// Print all the commands
+    #[clap(visible_alias = "ls")]
     Print {
         /// Print commands in JSON format
         #[clap(short, long)]
         json: bool,
+
+        /// Wrap the JSON output with the directory and config it was generated for
+        #[clap(long, requires = "json")]
+        with_meta: bool,
+
+        /// Highlight the shell syntax of the commands
+        #[clap(long, conflicts_with = "json")]
+        highlight: bool,
+
+        /// Group the commands by the project they are inherited from
+        #[clap(long, conflicts_with = "json")]
+        tree: bool,
+
+        /// The order to list the commands in
+        #[clap(long, value_enum, default_value_t, conflicts_with = "json")]
+        sort: Sort,
+
+        /// Only list the commands whose name matches any of these globs, e.g. `deploy-*`
+        patterns: Vec<String>,
+    },
+
+    /// Print the names of the available commands and groups, one per line, for shell completions.
+    /// Unlike `ls`, the output of this command is stable.
+    #[clap(hide = true)]
+    CompleteAliases {
+        /// Only print the names that start with this prefix, e.g. what was typed so far
+        #[clap(default_value = "")]
+        prefix: String,
+
+        /// Follow each name by a tab and the command it runs
+        #[clap(long)]
+        descriptions: bool,
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The order to list commands in.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum Sort {
+    /// Alphabetically by the name of the command
+    #[default]
+    Name,
+
+    /// Alphabetically by the command itself
+    Command,
+}
+
+/// The formats a project can be exported in.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+enum ExportFormat {
+    /// The commands of the project itself, to share with others
+    #[default]
+    Json,
+
+    /// A POSIX shell script that runs all the resolved commands, for machines without taco
+    Sh,
+}
+
+/// How to list the commands of a project.
+#[derive(Default)]
+struct ListOptions {
+    highlight: bool,
+    sort: Sort,
+
+    /// Only list the commands whose name matches any of these globs.
+    patterns: Vec<String>,
+}
+
+impl ListOptions {
+    /// Whether the command with the given name should be listed.
+    fn includes(&self, name: &str) -> bool {
+        self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| shell::glob_matches(pattern, name))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Config {
     /// A project can map to other projects so that it can inherit values from that other project.
     /// This allows you to define some common projects like "webdev" or "rust" or anything you
@@ -78,6 +524,172 @@ struct Config {
     /// for the command name and the command + arguments to run.
     #[serde(default)]
     projects: BTreeMap<String, Project>,
+
+    /// A map keyed by the location of each project, the value is another map with the name of each
+    /// group and the names of the commands it runs in sequence. Groups live in their own namespace
+    /// and are inherited the same way as commands are.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    groups: BTreeMap<String, Groups>,
+
+    /// An opt-in template engine to interpret the commands with. When it is not set, the passthrough
+    /// arguments are appended to the command instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template_engine: Option<TemplateEngine>,
+
+    /// A map keyed by the location of each project, the value is a shell snippet (or the path to a
+    /// file) that is sourced before running any of the commands defined by that project.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    shell_env: BTreeMap<String, String>,
+
+    /// A map keyed by the location of each project, the value is a map of variables that are
+    /// available in the templates of the commands. They are inherited the same way as commands are.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    vars: BTreeMap<String, BTreeMap<String, String>>,
+
+    /// Reject passthrough arguments for commands that don't use them, unless a command defines its
+    /// own `strict_args`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    strict_args: bool,
+
+    /// The directory commands run in, unless a command defines its own `cwd_strategy`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cwd_strategy: Option<CwdStrategy>,
+
+    /// Store the projects in the home directory as `~/...` instead of their absolute path, so that
+    /// the config can be shared between machines with a different home directory. Keys starting
+    /// with `~` are always understood, regardless of this setting.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    home_relative_paths: bool,
+
+    /// The `.taco.json` of the directory the commands are resolved for, or of one of its parents.
+    /// It is not part of the config itself, so it is never written back.
+    #[serde(skip)]
+    local: Option<LocalProject>,
+}
+
+/// The name of the file with project local commands.
+const LOCAL_CONFIG_FILE: &str = ".taco.json";
+
+/// The commands that are defined in a `.taco.json` inside of a project, e.g. in a repository so
+/// that they are available regardless of where it is checked out. It has the same shape as the
+/// output of `taco export-project`.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct LocalProject {
+    /// The directory that contains the `.taco.json`.
+    #[serde(skip)]
+    dir: String,
+
+    #[serde(default)]
+    commands: Project,
+
+    #[serde(default)]
+    groups: Groups,
+
+    #[serde(default)]
+    vars: BTreeMap<String, String>,
+}
+
+/// The commands of a single project, without the absolute path it lives in so that it can be shared
+/// with others.
+#[derive(Debug, Serialize)]
+struct ProjectSnippet {
+    commands: Project,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    aliases: Option<Vec<String>>,
+}
+
+/// The resolved commands of a directory, together with where they came from so that tools can
+/// cache the output.
+#[derive(Debug, Serialize)]
+struct ProjectMeta {
+    pwd: String,
+    config: String,
+    commands: Project,
+
+    /// When the output was generated, in seconds since the unix epoch.
+    generated_at: u64,
+}
+
+/// The outcome of running a command, for tools that wrap taco.
+#[derive(Debug, Serialize)]
+struct RunResult {
+    alias: String,
+
+    /// The command after rendering it and attaching the passthrough arguments.
+    command: String,
+    cwd: String,
+    exit_code: i32,
+    duration_ms: u128,
+}
+
+/// Where a resolved command comes from, for tools that need to know.
+#[derive(Debug, Serialize)]
+struct Provenance {
+    alias: String,
+    command: String,
+
+    /// The key of the project that defines the command, a directory or a predefined project.
+    source: String,
+
+    /// The directory that aliased the source, if the command is inherited through an alias.
+    via_alias: Option<String>,
+}
+
+/// A project that is known to the config, for an overview of all of them.
+#[derive(Debug, Serialize)]
+struct ProjectSummary {
+    path: String,
+    commands: usize,
+    aliases: Vec<String>,
+
+    /// Whether this is the project of the current directory.
+    current: bool,
+}
+
+/// A project that contributes commands to another location.
+#[derive(Debug, Clone)]
+struct Source {
+    /// The key of the project in the config, this is the path of a directory or the name of a
+    /// predefined project.
+    key: String,
+
+    /// The directory that aliased this project, if it was inherited through an alias.
+    via: Option<String>,
+
+    /// Whether this is the `.taco.json` in the directory of the key, instead of the config.
+    local: bool,
+}
+
+impl Source {
+    /// The directory of the project. Predefined projects don't live in a directory, so they use the
+    /// directory that aliased them instead.
+    fn directory(&self) -> &str {
+        match (Path::new(&self.key).is_absolute(), &self.via) {
+            (false, Some(via)) => via,
+            _ => &self.key,
+        }
+    }
+
+    /// The key of the project, or the path of the `.taco.json` it is defined in.
+    fn name(&self) -> String {
+        match self.local {
+            true => Path::new(&self.key)
+                .join(LOCAL_CONFIG_FILE)
+                .display()
+                .to_string(),
+            false => self.key.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.via {
+            Some(via) => write!(f, "{} (aliased in {})", self.name(), via),
+            None => write!(f, "{}", self.name()),
+        }
+    }
 }
 
 impl Config {
@@ -85,7 +697,76 @@ impl Config {
         Config {
             aliases: BTreeMap::new(),
             projects: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            template_engine: None,
+            shell_env: BTreeMap::new(),
+            vars: BTreeMap::new(),
+            strict_args: false,
+            cwd_strategy: None,
+            home_relative_paths: false,
+            local: None,
+        }
+    }
+
+    /// The commands of a source, either from the config or from the `.taco.json` of a directory.
+    fn project_of(&self, source: &Source) -> Option<&Project> {
+        match source.local {
+            true => self.local.as_ref().map(|local| &local.commands),
+            false => self.projects.get(&source.key),
+        }
+    }
+
+    /// The commands that the project defines itself, in the config as well as in its `.taco.json`.
+    /// The latter win, the same as when the commands are resolved.
+    fn own_commands(&self, project: &str) -> Result<Project> {
+        let path = fs::canonicalize(project)?;
+        let global = self.projects.get(path.to_str().unwrap());
+        let local = self
+            .local
+            .as_ref()
+            .filter(|local| Path::new(&local.dir) == path);
+
+        if global.is_none() && local.is_none() {
+            return Err(eyre!("Project not found: {}", project));
+        }
+
+        let mut commands = global.cloned().unwrap_or_default();
+        if let Some(local) = local {
+            commands.extend(local.commands.clone());
+        }
+
+        Ok(commands)
+    }
+
+    /// The config the way it is written to disk, with the projects in the home directory as
+    /// `~/...` when `home_relative_paths` is enabled.
+    fn stored(&self) -> Config {
+        let mut config = self.clone();
+        if config.home_relative_paths {
+            config.map_paths(contract_home);
         }
+        config
+    }
+
+    /// Rewrite the paths the projects are keyed by, including the projects that are aliased.
+    fn map_paths(&mut self, map: impl Fn(&str) -> String) {
+        fn keys<V>(values: &mut BTreeMap<String, V>, map: &impl Fn(&str) -> String) {
+            *values = std::mem::take(values)
+                .into_iter()
+                .map(|(key, value)| (map(&key), value))
+                .collect();
+        }
+
+        keys(&mut self.aliases, &map);
+        for aliases in self.aliases.values_mut() {
+            for alias in aliases {
+                *alias = map(alias);
+            }
+        }
+        keys(&mut self.projects, &map);
+        keys(&mut self.groups, &map);
+        keys(&mut self.shell_env, &map);
+        keys(&mut self.vars, &map);
     }
 
     /// Get the list of aliases for a project
@@ -99,9 +780,79 @@ impl Config {
 
         self.aliases.get_mut(key).unwrap().push(alias.to_string());
 
+        // Refuse aliases that would (indirectly) alias themselves
+        self.resolution_order(&path)?;
+
         Ok(())
     }
 
+    /// Remove an alias from a project, and the project from the aliases when it was its last one.
+    /// Returns whether the alias existed.
+    fn remove_alias(&mut self, project: &str, alias: &str) -> Result<bool> {
+        let path = fs::canonicalize(project)?;
+        let key = path.to_str().unwrap();
+
+        let Some(aliases) = self.aliases.get_mut(key) else {
+            return Ok(false);
+        };
+
+        let count = aliases.len();
+        aliases.retain(|existing| existing != alias);
+        let removed = aliases.len() < count;
+
+        if aliases.is_empty() {
+            self.aliases.remove(key);
+        }
+
+        Ok(removed)
+    }
+
+    /// Rename a (predefined) project, and update all the aliases that refer to it. Its commands,
+    /// groups, shell environment and variables move along. Returns the amount of projects whose
+    /// aliases were updated.
+    fn rename_project(&mut self, old: &str, new: &str) -> Result<usize> {
+        if self.projects.contains_key(new) {
+            return Err(eyre!("Project already exists: {}", new));
+        }
+
+        let mut found = false;
+        if let Some(project) = self.projects.remove(old) {
+            self.projects.insert(new.to_string(), project);
+            found = true;
+        }
+
+        if let Some(groups) = self.groups.remove(old) {
+            self.groups.insert(new.to_string(), groups);
+            found = true;
+        }
+
+        if let Some(env) = self.shell_env.remove(old) {
+            self.shell_env.insert(new.to_string(), env);
+            found = true;
+        }
+
+        if let Some(vars) = self.vars.remove(old) {
+            self.vars.insert(new.to_string(), vars);
+            found = true;
+        }
+
+        let mut updated = 0;
+        for aliases in self.aliases.values_mut() {
+            if aliases.iter().any(|alias| alias == old) {
+                for alias in aliases.iter_mut().filter(|alias| *alias == old) {
+                    *alias = new.to_string();
+                }
+                updated += 1;
+            }
+        }
+
+        if !found && updated == 0 {
+            return Err(eyre!("Project not found: {}", old));
+        }
+
+        Ok(updated)
+    }
+
     /// Get the current project's commands.
     /// Note: it will not merge the commands with any parent projects.
     fn get_project_mut(&mut self, project: &str) -> Result<&mut Project> {
@@ -114,82 +865,459 @@ impl Config {
     }
 
     /// Get the resolved commands, these are the commands of the current project, merged with all
-    /// the parent projects.
-    fn resolve_project(&mut self, project: &str) -> Result<Project> {
+    /// the parent projects. Commands use the variant of the environment in `TACO_ENV`, if any.
+    fn resolve_project(&self, project: &str) -> Result<Project> {
+        let environment = std::env::var("TACO_ENV").ok();
+        self.resolve_project_for(project, environment.as_deref())
+    }
+
+    /// Get the resolved commands for the given environment. Commands without a variant for the
+    /// environment fall back to their base command.
+    fn resolve_project_for(&self, project: &str, environment: Option<&str>) -> Result<Project> {
         let path = fs::canonicalize(project)?;
         let mut commands: Project = BTreeMap::new();
 
-        // Commands + aliases from parent directories
-        let mut parent: Vec<&str> = vec![];
-        for part in path.iter() {
-            parent.push(part.to_str().unwrap());
-            let mut project_path = parent.join("/");
-
-            // Drop double leading /
-            if project_path.len() > 1 {
-                project_path = (&project_path)[1..].to_owned();
-            }
-
-            if let Some(other) = self.aliases.get(&project_path) {
-                for alias in other {
-                    if let Some(project) = self.projects.get(alias) {
-                        for (key, value) in project {
-                            commands.insert(key.to_owned(), value.to_owned());
-                        }
-                    }
+        for source in self.resolution_order(&path)? {
+            if let Some(project) = self.project_of(&source) {
+                for (key, value) in project {
+                    commands.insert(key.to_owned(), value.to_owned());
                 }
             }
+        }
 
-            // Merge commands with parent
-            if self.projects.contains_key(&project_path) {
-                for (key, value) in self.projects.get_mut(&project_path).unwrap() {
-                    commands.insert(key.to_owned(), value.to_owned());
+        if let Some(environment) = environment {
+            for spec in commands.values_mut() {
+                if let Some(command) = spec.options.environments.get(environment) {
+                    spec.command = command.clone();
                 }
             }
         }
 
         Ok(commands)
     }
-}
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
-    ensure_config_exists()?;
+    /// Get the resolved groups, these are the groups of the current project, merged with all the
+    /// parent projects.
+    fn resolve_groups(&self, project: &str) -> Result<Groups> {
+        let path = fs::canonicalize(project)?;
+        let mut groups: Groups = BTreeMap::new();
 
-    let pwd = fs::canonicalize(&args.pwd)?.to_str().unwrap().to_string();
+        for source in self.resolution_order(&path)? {
+            let groups_of = match source.local {
+                true => self.local.as_ref().map(|local| &local.groups),
+                false => self.groups.get(&source.key),
+            };
+            if let Some(project) = groups_of {
+                for (key, value) in project {
+                    groups.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
 
-    match &args.command {
-        Some(Commands::Add { name, arguments }) => {
-            let mut config = read_config()?;
-            let command = &arguments.join(" ");
+        Ok(groups)
+    }
 
-            match config.get_project_mut(&pwd) {
-                Ok(project) => {
-                    if let Some(existing) = project.get(name) {
-                        println!(
-                            "Command \"{}\" already exists with value \"{}\"",
-                            name.blue(),
-                            existing.blue()
-                        );
+    /// Get the variables that are available in the templates of the commands of a project.
+    fn resolve_vars(&self, project: &str) -> Result<BTreeMap<String, String>> {
+        let path = fs::canonicalize(project)?;
+        let mut vars = BTreeMap::new();
 
-                        if !confirm(&format!(
-                            "Do you want to override it with \"{}\"?",
-                            command.blue()
-                        )) {
+        for source in self.resolution_order(&path)? {
+            let vars_of = match source.local {
+                true => self.local.as_ref().map(|local| &local.vars),
+                false => self.vars.get(&source.key),
+            };
+            if let Some(project) = vars_of {
+                vars.extend(project.clone());
+            }
+        }
+
+        Ok(vars)
+    }
+
+    /// Get the projects that contribute commands to the current project, in the order they are
+    /// merged in.
+    fn resolve_sources(&self, project: &str) -> Result<Vec<(Source, &Project)>> {
+        let path = fs::canonicalize(project)?;
+
+        Ok(self
+            .resolution_order(&path)?
+            .into_iter()
+            .filter_map(|source| {
+                let project = self.project_of(&source)?;
+                Some((source, project))
+            })
+            .collect())
+    }
+
+    /// The project that defines the command, this is the last project in the resolution order that
+    /// has it.
+    fn defining_source(&self, project: &str, alias: &str) -> Result<Option<Source>> {
+        Ok(self
+            .resolve_sources(project)?
+            .into_iter()
+            .rev()
+            .find(|(_, project)| project.contains_key(alias))
+            .map(|(source, _)| source))
+    }
+
+    /// Get the directory to run the command in, based on the `cwd_strategy` of the command or the
+    /// default of the config, and the `cwd` of the command relative to that. The command is
+    /// resolved from `project`, which usually is the directory taco was invoked in as well.
+    fn resolve_cwd(
+        &self,
+        project: &str,
+        invoked_in: &str,
+        alias: &str,
+        options: &CommandOptions,
+    ) -> Result<String> {
+        let base = self.resolve_cwd_strategy(project, invoked_in, alias, options)?;
+        let Some(cwd) = &options.cwd else {
+            return Ok(base);
+        };
+
+        let dir = Path::new(&base).join(cwd);
+        match dir.is_dir() {
+            true => Ok(fs::canonicalize(dir)?.to_str().unwrap().to_owned()),
+            false => Err(eyre!(
+                "\"{}\" runs in {}, but that directory does not exist.",
+                alias,
+                dir.display()
+            )),
+        }
+    }
+
+    fn resolve_cwd_strategy(
+        &self,
+        project: &str,
+        invoked_in: &str,
+        alias: &str,
+        options: &CommandOptions,
+    ) -> Result<String> {
+        match options
+            .cwd_strategy
+            .or(self.cwd_strategy)
+            .unwrap_or_default()
+        {
+            CwdStrategy::Invocation => Ok(invoked_in.to_string()),
+            CwdStrategy::Definition => self.definition_dir(project, alias),
+            CwdStrategy::GitRoot => {
+                let mut git = Command::new("git");
+                git.args(["rev-parse", "--show-toplevel"])
+                    .current_dir(project);
+                let output = git.output()?;
+                trace::log(&git, output.status.code());
+
+                match output.status.success() {
+                    true => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+                    false => Err(eyre!(
+                        "\"{}\" runs in the git root, but {} is not a git repository.",
+                        alias,
+                        project
+                    )),
+                }
+            }
+        }
+    }
+
+    /// The directory of the project that defines the command, paths in its options are relative to
+    /// it.
+    fn definition_dir(&self, project: &str, alias: &str) -> Result<String> {
+        Ok(self
+            .defining_source(project, alias)?
+            .map(|source| source.directory().to_string())
+            .unwrap_or_else(|| project.to_string()))
+    }
+
+    /// Get the marker file of a command that should only run once. It is relative to the directory
+    /// of the project that defines the command.
+    fn resolve_once_marker(&self, project: &str, alias: &str, marker: &str) -> Result<PathBuf> {
+        Ok(Path::new(&self.definition_dir(project, alias)?).join(marker))
+    }
+
+    /// Get the shell snippet to run before the command, as defined by the project that defines the
+    /// command. It doesn't apply to commands of other projects.
+    fn resolve_shell_env(&self, project: &str, alias: &str) -> Result<Option<String>> {
+        let source = self.defining_source(project, alias)?;
+
+        // A `.taco.json` has no shell environment of its own
+        let source = source.filter(|source| !source.local);
+        let (source, env) = match source.and_then(|s| self.shell_env.get(&s.key).map(|e| (s, e))) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        // Paths are relative to the directory of the project, or to the directory that aliased it.
+        let base = source.via.as_deref().unwrap_or(&source.key);
+        let file = Path::new(base).join(env);
+
+        match file.is_file() {
+            true => Ok(Some(format!(". {}", shell::quote(file.to_str().unwrap())))),
+            false => Ok(Some(env.to_owned())),
+        }
+    }
+
+    /// All the projects that contribute to the given path, in the order they should be merged in.
+    /// Later projects override earlier ones: deeper directories win over their parents, and a
+    /// directory wins over the projects it (transitively) aliases.
+    fn resolution_order(&self, path: &Path) -> Result<Vec<Source>> {
+        let mut keys = vec![];
+
+        // Commands + aliases from parent directories. The path is canonical, and so are all of its
+        // ancestors, which means they can be looked up as is.
+        let ancestors: Vec<&Path> = path.ancestors().collect();
+        for ancestor in ancestors.into_iter().rev() {
+            let project_path = ancestor.to_str().unwrap().to_owned();
+
+            if let Some(other) = self.aliases.get(&project_path) {
+                for alias in other {
+                    let mut chain = vec![project_path.clone()];
+                    self.expand_alias(alias, &project_path, &mut chain, &mut keys)?;
+                }
+            }
+
+            // Merge commands with parent
+            keys.push(Source {
+                key: project_path,
+                via: None,
+                local: false,
+            });
+        }
+
+        // The commands of a `.taco.json` win over the ones in the config
+        if let Some(local) = self.local.as_ref().filter(|l| path.starts_with(&l.dir)) {
+            keys.push(Source {
+                key: local.dir.clone(),
+                via: None,
+                local: true,
+            });
+        }
+
+        Ok(keys)
+    }
+
+    /// Add an aliased project to the sources, preceded by the projects it aliases itself. The
+    /// `chain` contains the projects that led to this alias, to detect aliases that (indirectly)
+    /// alias themselves.
+    fn expand_alias(
+        &self,
+        alias: &str,
+        via: &str,
+        chain: &mut Vec<String>,
+        keys: &mut Vec<Source>,
+    ) -> Result<()> {
+        if chain.iter().any(|key| key == alias) {
+            chain.push(alias.to_string());
+            return Err(eyre!("Aliases form a cycle: {}", chain.join(" \u{2192} ")));
+        }
+
+        chain.push(alias.to_string());
+        for nested in self.aliases.get(alias).into_iter().flatten() {
+            self.expand_alias(nested, via, chain, keys)?;
+        }
+        chain.pop();
+
+        keys.push(Source {
+            key: alias.to_owned(),
+            via: Some(via.to_owned()),
+            local: false,
+        });
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    // Commands that invoke taco themselves should use the same config
+    if args.portable {
+        std::env::set_var("TACO_PORTABLE", "1");
+    }
+    if let Some(config) = &args.config {
+        std::env::set_var("TACO_CONFIG", std::env::current_dir()?.join(config));
+    }
+    if let Some(trace) = &args.trace_exec {
+        std::env::set_var(trace::VARIABLE, std::env::current_dir()?.join(trace));
+    }
+    if args.no_color {
+        colored::control::set_override(false);
+        std::env::set_var("TACO_NO_COLOR", "1");
+    }
+
+    ensure_config_exists()?;
+
+    let pwd = resolve_pwd(&args.pwd)?;
+
+    let code = run(&args, &pwd, &mut Store::File)?;
+    if code != 0 {
+        std::process::exit(code);
+    }
+
+    Ok(())
+}
+
+/// Where the subcommands read the config from and write it to.
+enum Store {
+    /// The config file.
+    File,
+
+    /// A config that is kept in memory, e.g. the one the invocations of a batch share.
+    Memory { config: Box<Config>, changed: bool },
+}
+
+impl Store {
+    fn read(&self) -> Result<Config> {
+        match self {
+            Store::File => read_config(),
+            Store::Memory { config, .. } => Ok(Config::clone(config)),
+        }
+    }
+
+    /// Read the config to resolve the commands of the given directory, see `read_config_for`.
+    fn read_for(&self, pwd: &str) -> Result<Config> {
+        match self {
+            Store::File => read_config_for(pwd),
+            Store::Memory { config, .. } => Ok(Config {
+                local: read_local_project(Path::new(pwd))?,
+                ..Config::clone(config)
+            }),
+        }
+    }
+
+    fn write(&mut self, updated: &Config) -> Result<()> {
+        match self {
+            Store::File => write_config(updated),
+            Store::Memory { config, changed } => {
+                **config = Config {
+                    local: None,
+                    ..updated.clone()
+                };
+                *changed = true;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Run the subcommand that was passed to taco, and return the code taco should exit with.
+fn run(args: &Cli, pwd: &str, store: &mut Store) -> Result<i32> {
+    let pwd = pwd.to_string();
+
+    match &args.command {
+        Some(Commands::Add {
+            name,
+            arguments,
+            append,
+            prepend,
+            force,
+            no_overwrite,
+            capture_env,
+        }) => {
+            let mut config = store.read()?;
+
+            if append.is_some() || prepend.is_some() {
+                let existing = config
+                    .get_project_mut(&pwd)?
+                    .get_mut(name)
+                    .ok_or_else(|| eyre!("Command \"{}\" does not exist", name))?;
+
+                if let Some(fragment) = append {
+                    existing.command = format!("{} {}", existing.command, fragment);
+                }
+
+                if let Some(fragment) = prepend {
+                    existing.command = format!("{} {}", fragment, existing.command);
+                }
+
+                let command = existing.command.clone();
+                store.write(&config)?;
+
+                println!(
+                    "Updated \"{}\" to \"{}\" in {}",
+                    name.blue(),
+                    command.blue(),
+                    pwd.dimmed()
+                );
+                return Ok(0);
+            }
+
+            // Write longer commands in the editor instead, starting from the existing command
+            let command = &match arguments.is_empty() {
+                true => {
+                    let Some(editor) = editor() else {
+                        return Err(eyre!(
+                            "No command given, pass it after `--` or set $VISUAL or $EDITOR to write it in your editor."
+                        ));
+                    };
+                    let existing = config
+                        .projects
+                        .get(&pwd)
+                        .and_then(|project| project.get(name))
+                        .map(|spec| spec.command.clone())
+                        .unwrap_or_default();
+
+                    match edit_in(&editor, ".sh", &existing)? {
+                        Some(command) if !command.trim().is_empty() => command,
+                        _ => {
+                            println!("{}", "Aborted!".red());
+                            return Ok(1);
+                        }
+                    }
+                }
+                false => arguments.join(" "),
+            };
+            let mut spec = CommandSpec::new(command.clone());
+
+            // Freeze the environment the command was authored in
+            for variable in capture_env {
+                match std::env::var(variable) {
+                    Ok(value) => {
+                        spec.options.env.insert(variable.clone(), value);
+                    }
+                    Err(_) => eprintln!(
+                        "{}",
+                        format!("\"{}\" is not set, it won't be captured.", variable).yellow()
+                    ),
+                }
+            }
+
+            match config.get_project_mut(&pwd) {
+                Ok(project) => {
+                    if project.contains_key(name) && *no_overwrite {
+                        return Err(eyre!(
+                            "Command \"{}\" already exists, not overwritten (use --force)",
+                            name
+                        ));
+                    }
+
+                    if let Some(existing) = project.get(name).filter(|_| !force) {
+                        println!(
+                            "Command \"{}\" already exists with value \"{}\"",
+                            name.blue(),
+                            existing.command.blue()
+                        );
+
+                        if !args.yes
+                            && !confirm(&format!(
+                                "Do you want to override it with \"{}\"?",
+                                command.blue()
+                            ))
+                        {
                             println!("{}", "Aborted!".red());
-                            return Ok(());
+                            return Ok(1);
                         }
                     }
 
                     // Akshually insert the new command.
-                    project.insert(name.to_string(), command.clone());
-                    write_config(&config)?;
+                    project.insert(name.to_string(), spec);
+                    store.write(&config)?;
                 }
                 Err(_) => {
                     let mut project = BTreeMap::new();
-                    project.insert(name.to_string(), command.clone());
+                    project.insert(name.to_string(), spec);
                     config.projects.insert(pwd.to_string(), project);
-                    write_config(&config)?;
+                    store.write(&config)?;
                 }
             }
 
@@ -199,143 +1327,1921 @@ fn main() -> Result<()> {
                 &command.blue(),
                 pwd.dimmed()
             );
-            Ok(())
+            Ok(0)
+        }
+        Some(Commands::Clone { name, new_name }) => {
+            // The whole config is written again, but the commands of a `.taco.json` can be copied
+            let mut config = store.read()?;
+            config.local = read_local_project(Path::new(&pwd))?;
+            // Copy the base command, not the variant of the current environment
+            let spec = config
+                .resolve_project_for(&pwd, None)?
+                .remove(name)
+                .ok_or_else(|| eyre!("Command \"{}\" does not exist", name))?;
+
+            if config.get_project_mut(&pwd).is_err() {
+                config.projects.insert(pwd.to_string(), BTreeMap::new());
+            }
+            let project = config.get_project_mut(&pwd)?;
+
+            if let Some(existing) = project.get(new_name) {
+                println!(
+                    "Command \"{}\" already exists with value \"{}\"",
+                    new_name.blue(),
+                    existing.command.blue()
+                );
+
+                if !args.yes
+                    && !confirm(&format!(
+                        "Do you want to override it with a copy of \"{}\"?",
+                        name.blue()
+                    ))
+                {
+                    println!("{}", "Aborted!".red());
+                    return Ok(1);
+                }
+            }
+
+            project.insert(new_name.to_string(), spec);
+            store.write(&config)?;
+
+            println!(
+                "Copied \"{}\" to \"{}\" in {}",
+                name.blue(),
+                new_name.blue(),
+                pwd.dimmed()
+            );
+            Ok(0)
+        }
+        Some(Commands::Rename { from, to }) => {
+            let mut config = store.read()?;
+            let project = config.get_project_mut(&pwd)?;
+
+            if !project.contains_key(from) {
+                println!("Command \"{}\" does not exist.\n", from.blue());
+                print_project_commands(project, &Groups::new(), &ListOptions::default());
+                return Ok(1);
+            }
+
+            if let Some(existing) = project.get(to) {
+                println!(
+                    "Command \"{}\" already exists with value \"{}\"",
+                    to.blue(),
+                    existing.command.blue()
+                );
+
+                if !args.yes
+                    && !confirm(&format!(
+                        "Do you want to override it with \"{}\"?",
+                        from.blue()
+                    ))
+                {
+                    println!("{}", "Aborted!".red());
+                    return Ok(1);
+                }
+            }
+
+            let spec = project.remove(from).unwrap();
+            project.insert(to.to_string(), spec);
+
+            // Keep the groups of the project pointing at the command
+            if let Some(groups) = config.groups.get_mut(&pwd) {
+                for member in groups.values_mut().flatten() {
+                    if member == from {
+                        *member = to.to_string();
+                    }
+                }
+            }
+
+            store.write(&config)?;
+
+            println!(
+                "Renamed \"{}\" to \"{}\" in {}",
+                from.blue(),
+                to.blue(),
+                pwd.dimmed()
+            );
+            Ok(0)
         }
-        Some(Commands::Alias { name }) => {
-            let mut config = read_config()?;
+        Some(Commands::Alias {
+            name,
+            to,
+            rename,
+            dry_run,
+        }) => {
+            let mut config = store.read()?;
+
+            if let Some([old, new]) = rename.as_deref() {
+                let updated = config.rename_project(old, new)?;
+                store.write(&config)?;
+                println!(
+                    "Renamed \"{}\" to \"{}\" {}",
+                    old.blue(),
+                    new.blue(),
+                    format!(
+                        "({} project{} updated)",
+                        updated,
+                        match updated {
+                            1 => "",
+                            _ => "s",
+                        }
+                    )
+                    .dimmed()
+                );
+                return Ok(0);
+            }
+
+            if *dry_run {
+                let target = match to {
+                    Some(to) => fs::canonicalize(to)?.to_str().unwrap().to_string(),
+                    None => name.clone().unwrap(),
+                };
+
+                let before = config.resolve_project(&pwd)?;
+                config.add_alias(&pwd, &target)?;
+                let after = config.resolve_project(&pwd)?;
+
+                print_alias_preview(
+                    &target,
+                    config.projects.get(&target).unwrap_or(&BTreeMap::new()),
+                    &before,
+                    &after,
+                );
+                return Ok(0);
+            }
+
+            if let Some(to) = to {
+                let target = fs::canonicalize(to)?.to_str().unwrap().to_string();
+                if !config.projects.contains_key(&target) {
+                    return Err(eyre!("Project not found: {}", target));
+                }
+
+                config.add_alias(&pwd, &target)?;
+                store.write(&config)?;
+                println!(
+                    "Added the commands of {} in {}",
+                    target.blue(),
+                    pwd.dimmed()
+                );
+                return Ok(0);
+            }
+
+            let name = name.as_ref().unwrap();
             config.add_alias(&pwd, name)?;
-            write_config(&config)?;
+            store.write(&config)?;
             println!("Added \"{}\" capabilities in {}", name.blue(), pwd.dimmed());
-            Ok(())
+            Ok(0)
+        }
+        Some(Commands::Unalias { name }) => {
+            let mut config = store.read()?;
+
+            // Aliases to other directories are stored by their canonical path
+            let alias = match Path::new(name).is_dir() {
+                true => fs::canonicalize(name)?.to_str().unwrap().to_string(),
+                false => name.clone(),
+            };
+
+            if config.remove_alias(&pwd, &alias)? || config.remove_alias(&pwd, name)? {
+                store.write(&config)?;
+                println!("Removed alias \"{}\" from {}", name.blue(), pwd.dimmed());
+                return Ok(0);
+            }
+
+            println!("Alias \"{}\" does not exist.\n", name.blue());
+            match config.aliases.get(&pwd) {
+                Some(aliases) => {
+                    println!("Available aliases:\n");
+                    for alias in aliases {
+                        println!("  {}", alias.blue());
+                    }
+                }
+                None => println!(
+                    "{}",
+                    " \u{2219} There are no aliases in this directory.".red()
+                ),
+            }
+
+            Ok(1)
+        }
+        Some(Commands::Record { name }) => {
+            let shell = shell::default_shell();
+            println!(
+                "Run the command you want to save as \"{}\", and exit the shell when you're happy with the result.\n",
+                name.blue()
+            );
+
+            let command = match record::session(&shell, &pwd)? {
+                Some(mut commands) => {
+                    if commands.len() > 1 {
+                        println!(
+                            "\n{}",
+                            format!("{} commands ran, the last one is saved.", commands.len())
+                                .dimmed()
+                        );
+                    }
+                    commands.pop().unwrap_or_default()
+                }
+
+                // Shells without a hook to record the commands with run a single command instead
+                None => record_single(&shell, &pwd)?,
+            };
+
+            if command.is_empty() {
+                println!("{}", "Aborted!".red());
+                return Ok(1);
+            }
+
+            println!();
+
+            let mut config = store.read()?;
+            let project = config.projects.entry(pwd.to_string()).or_default();
+            let message = match project.get(name) {
+                Some(existing) => format!(
+                    "Do you want to save \"{}\" as \"{}\"? It overrides \"{}\"",
+                    command.blue(),
+                    name.blue(),
+                    existing.command.blue()
+                ),
+                None => format!(
+                    "Do you want to save \"{}\" as \"{}\"?",
+                    command.blue(),
+                    name.blue()
+                ),
+            };
+
+            if !args.yes && !confirm(&message) {
+                println!("{}", "Aborted!".red());
+                return Ok(1);
+            }
+
+            project.insert(name.to_string(), CommandSpec::new(command.clone()));
+            store.write(&config)?;
+
+            println!(
+                "Aliased \"{}\" to \"{}\" in {}",
+                name.blue(),
+                command.blue(),
+                pwd.dimmed()
+            );
+            Ok(0)
+        }
+        Some(Commands::Group { name, commands }) => {
+            let mut config = store.read()?;
+            let groups = config.groups.entry(pwd.to_string()).or_default();
+
+            if let Some(existing) = groups.get(name) {
+                println!(
+                    "Group \"{}\" already exists with commands \"{}\"",
+                    name.blue(),
+                    existing.join(" ").blue()
+                );
+
+                if !args.yes
+                    && !confirm(&format!(
+                        "Do you want to override it with \"{}\"?",
+                        commands.join(" ").blue()
+                    ))
+                {
+                    println!("{}", "Aborted!".red());
+                    return Ok(1);
+                }
+            }
+
+            groups.insert(name.to_string(), commands.clone());
+            store.write(&config)?;
+
+            println!(
+                "Grouped \"{}\" as \"{}\" in {}",
+                commands.join(" ").blue(),
+                name.blue(),
+                pwd.dimmed()
+            );
+            Ok(0)
         }
-        Some(Commands::Remove { name }) => {
-            let mut config = read_config()?;
+        Some(Commands::Remove { name, interactive }) => {
+            let mut config = store.read()?;
             let project = config.get_project_mut(&pwd)?;
+
+            if *interactive {
+                let names = select_commands("Select the commands to remove", project)?;
+                if names.is_empty() {
+                    println!("{}", "Aborted!".red());
+                    return Ok(1);
+                }
+
+                let list = names
+                    .iter()
+                    .map(|name| format!("\"{}\"", name.blue()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if !args.yes && !confirm(&format!("Do you want to remove {}?", list)) {
+                    println!("{}", "Aborted!".red());
+                    return Ok(1);
+                }
+
+                for name in &names {
+                    project.remove(name);
+                }
+
+                store.write(&config)?;
+                println!("Removed aliases {}\n", list);
+
+                return Ok(0);
+            }
+
+            let name = name.as_ref().unwrap();
             match project.remove(name) {
                 Some(_) => {
-                    write_config(&config)?;
+                    store.write(&config)?;
                     println!("Removed alias \"{}\"\n", name.blue());
+                    Ok(0)
                 }
                 None => {
                     println!("Alias \"{}\" does not exist.\n", name.blue());
-                    print_project_commands(project);
+                    print_project_commands(project, &Groups::new(), &ListOptions::default());
+                    Ok(1)
+                }
+            }
+        }
+        Some(Commands::ExportProject {
+            path,
+            with_aliases,
+            format,
+        }) => {
+            let path = match path {
+                Some(path) => fs::canonicalize(path)?.to_str().unwrap().to_string(),
+                None => pwd.to_string(),
+            };
+            let config = store.read_for(&path)?;
+
+            if *format == ExportFormat::Sh {
+                if *with_aliases {
+                    return Err(eyre!(
+                        "--with-aliases is only supported for the JSON format."
+                    ));
+                }
+
+                println!("{}", export_script(&config, &path)?);
+                return Ok(0);
+            }
+
+            let aliases = match with_aliases {
+                true => config.aliases.get(&path).cloned(),
+                false => None,
+            };
+
+            let snippet = ProjectSnippet {
+                commands: config.own_commands(&path)?,
+                aliases,
+            };
+
+            println!("{}", serde_json::to_string_pretty(&snippet)?);
+
+            Ok(0)
+        }
+        Some(Commands::Export { json: _, toml }) => {
+            // The config as it is stored, without resolving any of the projects
+            let config = store.read()?.stored();
+
+            match toml {
+                true => print!("{}", toml::to_string_pretty(&config)?),
+                false => println!("{}", serde_json::to_string_pretty(&config)?),
+            }
+
+            Ok(0)
+        }
+        Some(Commands::Import { file, overwrite }) => {
+            let mut config = store.read()?;
+            let imported = match file {
+                Some(file) => parse_config(&fs::read_to_string(file)?, file)?,
+
+                // There is no extension to tell the format by, but JSON always starts with a `{`
+                None => {
+                    let contents = std::io::read_to_string(std::io::stdin())?;
+                    match contents.trim_start().starts_with('{') {
+                        true => parse_config(&contents, "stdin.json")?,
+                        false => parse_config(&contents, "stdin.toml")?,
+                    }
                 }
+            };
+
+            let changes = import_config(&mut config, imported, *overwrite || args.yes);
+            if changes == 0 {
+                println!("{}", "Nothing was imported.".dimmed());
+                return Ok(0);
+            }
+
+            store.write(&config)?;
+            println!(
+                "\nImported {} {}",
+                changes,
+                if changes == 1 { "change" } else { "changes" }
+            );
+
+            Ok(0)
+        }
+        Some(Commands::Ps) => {
+            let registry = processes::read_registry(&processes_file())?;
+
+            println!("Background processes:\n");
+
+            if registry.is_empty() {
+                println!("{}", " \u{2219} There are no background processes.\n".red());
             }
 
-            write_config(&config)?;
+            let mut running = vec![];
+            for process in registry {
+                let status = match process.is_running() {
+                    true => "running".green(),
+                    false => "exited".red(),
+                };
+
+                println!(
+                    "  {} {} {}\n    {}\n",
+                    process.alias.blue(),
+                    format!("(PID {})", process.pid).dimmed(),
+                    status,
+                    format!(
+                        "started {} ago \u{2219} {}",
+                        processes::elapsed_since(process.started_at),
+                        process.log.display()
+                    )
+                    .dimmed()
+                );
+
+                if process.is_running() {
+                    running.push(process);
+                }
+            }
+
+            // Forget about the processes that exited
+            processes::write_registry(&processes_file(), &running)?;
+
+            println!(
+                "{}",
+                format!(
+                    "{} running process{}",
+                    running.len(),
+                    match running.len() {
+                        1 => "",
+                        _ => "es",
+                    }
+                )
+                .dimmed()
+            );
+
+            Ok(0)
+        }
+        Some(Commands::Stop { target }) => {
+            let registry = processes::read_registry(&processes_file())?;
+            let (matching, rest): (Vec<_>, Vec<_>) = registry.into_iter().partition(|process| {
+                &process.alias == target || process.pid.to_string() == *target
+            });
+
+            if matching.is_empty() {
+                println!("There is no background process for \"{}\".", target.blue());
+                return Ok(0);
+            }
+
+            // Processes that exited are forgotten without signalling anything, their PID can belong
+            // to an unrelated process by now.
+            for process in &matching {
+                let pid = format!("(PID {})", process.pid).dimmed();
+                match process.is_running() {
+                    true => {
+                        process.stop()?;
+                        println!("Stopped \"{}\" {}", process.alias.blue(), pid);
+                    }
+                    false => println!("\"{}\" {} already exited", process.alias.blue(), pid),
+                }
+            }
+
+            processes::write_registry(&processes_file(), &rest)?;
+
+            Ok(0)
+        }
+        Some(Commands::Lint { name, all }) => {
+            let config = store.read_for(&pwd)?;
+            let project = config.resolve_project(&pwd)?;
+            let groups = config.resolve_groups(&pwd)?;
+            let shell = shell::default_shell();
+            let Some(names) = commands_to_check(name, *all, &project, &groups) else {
+                return Ok(1);
+            };
+
+            let mut invalid = 0;
+            for name in names {
+                let Some(spec) = project.get(name) else {
+                    println!("  {} {}", name.blue(), "does not exist".red());
+                    invalid += 1;
+                    continue;
+                };
+
+                match shell::check_syntax(&shell, &spec.command)? {
+                    None => println!("  {} {}", name.blue(), "ok".green()),
+                    Some(error) => {
+                        println!("  {} {}", name.blue(), error.red());
+                        invalid += 1;
+                    }
+                }
+            }
+
+            if invalid > 0 {
+                println!(
+                    "\n{}",
+                    format!(
+                        "{} command{} with syntax errors",
+                        invalid,
+                        match invalid {
+                            1 => "",
+                            _ => "s",
+                        }
+                    )
+                    .red()
+                );
+                return Ok(1);
+            }
+
+            Ok(0)
+        }
+        Some(Commands::Which { name, json }) => {
+            let config = store.read_for(&pwd)?;
+            let project = config.resolve_project(&pwd)?;
+
+            let Some(spec) = project.get(name) else {
+                println!("Command \"{}\" does not exist.", name.blue());
+                return Ok(1);
+            };
+
+            // Every project that defines the command, the last one wins
+            let sources: Vec<Source> = config
+                .resolve_sources(&pwd)?
+                .into_iter()
+                .filter(|(_, project)| project.contains_key(name))
+                .map(|(source, _)| source)
+                .collect();
+            let (source, overridden) = sources.split_last().unwrap();
+
+            if *json {
+                let provenance = Provenance {
+                    alias: name.clone(),
+                    command: spec.command.clone(),
+                    source: source.name(),
+                    via_alias: source.via.clone(),
+                };
+                println!("{}", serde_json::to_string_pretty(&provenance)?);
+                return Ok(0);
+            }
+
+            println!("  taco {}\n    {}\n", name.blue(), spec.command);
+            println!("  Defined in {}", source.to_string().green());
+            for source in overridden.iter().rev() {
+                println!("  {}", format!("Overrides {}", source).dimmed());
+            }
+
+            Ok(0)
+        }
+        Some(Commands::CheckCommand { name, all }) => {
+            let config = store.read_for(&pwd)?;
+            let project = config.resolve_project(&pwd)?;
+            let groups = config.resolve_groups(&pwd)?;
+
+            let Some(names) = commands_to_check(name, *all, &project, &groups) else {
+                return Ok(1);
+            };
+
+            let mut missing = 0;
+            for name in names {
+                let Some(spec) = project.get(name) else {
+                    println!("  {} {}", name.blue(), "does not exist".red());
+                    missing += 1;
+                    continue;
+                };
+                let Some(executable) = shell::executable(&spec.command) else {
+                    continue;
+                };
+
+                match shell::find_executable(executable, Path::new(&pwd)) {
+                    Some(path) => println!(
+                        "  {} {} {}",
+                        name.blue(),
+                        executable,
+                        format!("({})", path.display()).dimmed()
+                    ),
+                    None => {
+                        println!("  {} {} {}", name.blue(), executable, "not found".red());
+                        missing += 1;
+                    }
+                }
+            }
+
+            if missing > 0 {
+                println!(
+                    "\n{}",
+                    format!(
+                        "{} command{} can't run on this machine",
+                        missing,
+                        match missing {
+                            1 => "",
+                            _ => "s",
+                        }
+                    )
+                    .red()
+                );
+                return Ok(1);
+            }
+
+            Ok(0)
+        }
+        Some(Commands::Batch { file, transaction }) => {
+            let contents = fs::read_to_string(file)?;
+            let lines: Vec<&str> = contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .collect();
+
+            // Parse all the lines up front, so that a typo doesn't leave the batch half done
+            let invocations = lines
+                .iter()
+                .map(|line| {
+                    parse_batch_line(line)
+                        .wrap_err_with(|| format!("Invalid invocation \"{}\"", line))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            // The invocations share one config, which is only written once they are done. Relative
+            // paths in the invocations are relative to the directory of the batch.
+            let mut batch = Store::Memory {
+                config: Box::new(store.read()?),
+                changed: false,
+            };
+            let cwd = std::env::current_dir()?;
+            std::env::set_current_dir(&pwd)?;
+
+            let mut results = vec![];
+            for (line, invocation) in lines.iter().zip(&invocations) {
+                let code = resolve_pwd(&invocation.pwd)
+                    .and_then(|pwd| run(invocation, &pwd, &mut batch))
+                    .unwrap_or_else(|error| {
+                        eprintln!("Error: {:?}", error);
+                        1
+                    });
+
+                results.push((line, code));
+                if code != 0 {
+                    break;
+                }
+            }
+
+            std::env::set_current_dir(cwd)?;
+
+            println!("\nBatch summary:\n");
+            for (line, code) in &results {
+                match code {
+                    0 => println!("  {} {}", "\u{2713}".green(), line),
+                    _ => println!(
+                        "  {} {} {}",
+                        "\u{2717}".red(),
+                        line,
+                        format!("(exit code {})", code).dimmed()
+                    ),
+                }
+            }
+            for line in &lines[results.len()..] {
+                println!("  {} {}", "-".dimmed(), line.dimmed());
+            }
+
+            let failed = results.iter().any(|(_, code)| *code != 0);
+            match batch {
+                Store::Memory { .. } if failed && *transaction => {
+                    println!("\n{}", "The batch failed, nothing was changed.".yellow());
+                }
+                Store::Memory {
+                    config,
+                    changed: true,
+                } => store.write(&config)?,
+                _ => {}
+            }
+
+            Ok(i32::from(failed))
+        }
+        Some(Commands::Graph { format }) => {
+            let config = store.read_for(&pwd)?;
+            let project = config.resolve_project(&pwd)?;
+            let groups = config.resolve_groups(&pwd)?;
+
+            println!("{}", graph::render(&project, &groups, *format));
+
+            Ok(0)
+        }
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(*shell, &mut Cli::command(), "taco", &mut std::io::stdout());
+
+            // Complete the commands of the current directory as well, where the shell allows it
+            if let Some(script) = init::alias_completions(*shell) {
+                print!("{}", script);
+            }
+
+            Ok(0)
+        }
+        Some(Commands::Init {
+            shell,
+            no_completions,
+            cd_hook,
+            shims,
+        }) => {
+            let integration = init::Integration {
+                completions: !no_completions,
+                cd_hook: *cd_hook,
+                shims,
+            };
+            print!("{}", init::script(*shell, &integration)?);
+
+            Ok(0)
+        }
+        Some(Commands::List { json }) => {
+            let config = store.read()?;
+
+            let mut paths: Vec<&String> = config.projects.keys().collect();
+            paths.extend(config.aliases.keys());
+            paths.sort();
+            paths.dedup();
+
+            let projects: Vec<ProjectSummary> = paths
+                .into_iter()
+                .map(|path| ProjectSummary {
+                    path: path.clone(),
+                    commands: config.projects.get(path).map_or(0, |project| project.len()),
+                    aliases: config.aliases.get(path).cloned().unwrap_or_default(),
+                    current: *path == pwd,
+                })
+                .collect();
+
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&projects)?);
+                return Ok(0);
+            }
+
+            println!("Known projects:\n");
+
+            if projects.is_empty() {
+                println!("{}", " \u{2219} There are no projects yet.\n".red());
+            }
+
+            for project in &projects {
+                let path = match project.current {
+                    true => format!("{} (current)", project.path).green().bold(),
+                    false => project.path.blue(),
+                };
+                let commands = format!(
+                    "{} command{}",
+                    project.commands,
+                    match project.commands {
+                        1 => "",
+                        _ => "s",
+                    }
+                );
+
+                println!("  {} {}", path, commands.dimmed());
+                if !project.aliases.is_empty() {
+                    println!(
+                        "    {}",
+                        format!("aliased to {}", project.aliases.join(", ")).dimmed()
+                    );
+                }
+                println!();
+            }
+
+            Ok(0)
+        }
+        Some(Commands::Print {
+            json,
+            with_meta,
+            highlight,
+            tree,
+            sort,
+            patterns,
+        }) => {
+            let list = ListOptions {
+                highlight: *highlight,
+                sort: *sort,
+                patterns: patterns.clone(),
+            };
+            let config = store.read_for(&pwd)?;
+
+            let mut project = config.resolve_project(&pwd)?;
+            project.retain(|name, _| list.includes(name));
+
+            if *tree {
+                print_project_tree(&config.resolve_sources(&pwd)?, &project, &list);
+            } else if *with_meta {
+                let meta = ProjectMeta {
+                    pwd: pwd.clone(),
+                    config: config_file_location(),
+                    commands: project,
+                    generated_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+                };
+                println!("{}", serde_json::to_string_pretty(&meta)?);
+            } else if *json {
+                println!("{}", serde_json::to_string_pretty(&project)?);
+            } else {
+                let mut groups = config.resolve_groups(&pwd)?;
+                groups.retain(|name, _| list.includes(name));
+
+                print_project_commands(&project, &groups, &list)
+            }
+
+            Ok(0)
+        }
+        Some(Commands::Edit { project }) => {
+            let file_path = config_file_location();
+            let Some(editor) = editor() else {
+                println!(
+                    "Neither $VISUAL nor $EDITOR is set, the config lives at {}",
+                    file_path
+                );
+                return Ok(0);
+            };
+
+            if *project {
+                edit_project(&editor, &pwd, store)?;
+                return Ok(0);
+            }
+
+            // The errors of a previous attempt are added to the top as comments
+            let (extension, marker) = match is_toml(&file_path) {
+                true => (".toml", "# taco: "),
+                false => (".json", "// taco: "),
+            };
+
+            let mut contents = fs::read_to_string(&file_path)?;
+            loop {
+                let Some(edited) = edit_in(&editor, extension, &contents)? else {
+                    println!("{}", "No changes were saved.".dimmed());
+                    return Ok(0);
+                };
+
+                let edited = edited
+                    .lines()
+                    .skip_while(|line| line.starts_with(marker))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                match parse_config(&edited, &file_path) {
+                    Ok(config) => {
+                        store.write(&config)?;
+                        println!("Updated {}", file_path.dimmed());
+                        return Ok(0);
+                    }
+
+                    // Open the editor again, instead of throwing the edits away
+                    Err(error) => {
+                        let error = format!("{:#}", error)
+                            .lines()
+                            .chain(["The line numbers don't count these comments."])
+                            .map(|line| format!("{}{}\n", marker, line))
+                            .collect::<String>();
+                        contents = format!("{}{}", error, edited);
+                    }
+                }
+            }
+        }
+        Some(Commands::Uninstall) => {
+            let config_file = PathBuf::from(config_file_location());
+            let config_dir = config_file.parent().unwrap().to_path_buf();
+            let paths: Vec<PathBuf> = [config_file, processes_file(), logs_dir()]
+                .into_iter()
+                .filter(|path| path.exists())
+                .collect();
+
+            println!("This will permanently delete:\n");
+            for path in &paths {
+                println!("  {}", path.display().to_string().red());
+            }
+            println!();
+
+            if !args.yes && !confirm("Are you sure you want to remove all of taco's files?") {
+                println!("{}", "Aborted!".red());
+                return Ok(1);
+            }
+
+            for path in &paths {
+                match path.is_dir() {
+                    true => fs::remove_dir_all(path)?,
+                    false => fs::remove_file(path)?,
+                }
+                println!("Removed {}", path.display().to_string().dimmed());
+            }
+
+            // Only remove the directory itself when taco was the only one using it, e.g. not when
+            // the config lives next to the executable in portable mode.
+            if fs::remove_dir(&config_dir).is_ok() {
+                println!("Removed {}", config_dir.display().to_string().dimmed());
+            }
+
+            println!(
+                "\nThe taco executable itself was left in place at {}",
+                std::env::current_exe()?.display().to_string().dimmed()
+            );
+
+            Ok(0)
+        }
+        Some(Commands::CompleteAliases {
+            prefix,
+            descriptions,
+        }) => {
+            let config = store.read_for(&pwd)?;
+            let project = config.resolve_project(&pwd)?;
+            let groups = config.resolve_groups(&pwd)?;
+
+            let commands = project
+                .iter()
+                .map(|(name, spec)| (name, spec.command.clone()));
+            let groups = groups
+                .iter()
+                .map(|(name, members)| (name, members.join(" \u{2192} ")));
+
+            let names = commands
+                .chain(groups)
+                .filter(|(name, _)| name.starts_with(prefix.as_str()));
+            for (name, description) in names {
+                match descriptions {
+                    // Commands can span multiple lines, but completions expect a single line
+                    true => println!("{}\t{}", name, description.replace('\n', " ")),
+                    false => println!("{}", name),
+                }
+            }
+
+            Ok(0)
+        }
+        Some(Commands::Run {
+            exec,
+            alias,
+            arguments,
+        }) => run_alias(args, &exec.or(&args.exec), alias, arguments, &pwd, store),
+        None => {
+            let Some(alias) = &args.alias else {
+                print_help()?;
+                return Ok(0);
+            };
+
+            run_alias(args, &args.exec, alias, &args.arguments, &pwd, store)
+        }
+    }
+}
+
+/// Read a single command and run it, for `taco record` in shells that can't be recorded.
+fn record_single(shell: &str, pwd: &str) -> Result<String> {
+    print!("{} ", "$".dimmed());
+    std::io::stdout().flush()?;
+
+    let mut command = String::new();
+    std::io::stdin().read_line(&mut command)?;
+    let command = command.trim().to_string();
+    if command.is_empty() {
+        return Ok(command);
+    }
+
+    let mut cmd = Command::new(shell);
+    cmd.args(shell::shell_args(shell));
+    shell::arg(&mut cmd, shell, &command);
+
+    let status = cmd
+        .current_dir(pwd)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()?;
+    trace::log(&cmd, status.code());
+
+    if !status.success() {
+        println!("{}", format!("The command failed with {}", status).red());
+    }
+
+    Ok(command)
+}
+
+/// Parse a line of a batch as the arguments to taco. The flags that configure taco itself are
+/// rejected, because all the lines run in the same process against the same config.
+fn parse_batch_line(line: &str) -> Result<Cli> {
+    let words = shell::split(line)?;
+    let matches =
+        Cli::command().try_get_matches_from(std::iter::once("taco".to_string()).chain(words))?;
+
+    let passed = ["config", "portable", "no_color", "trace_exec"]
+        .into_iter()
+        .find(|id| matches.value_source(id) == Some(ValueSource::CommandLine));
+    if let Some(id) = passed {
+        return Err(eyre!(
+            "--{} applies to the whole batch, pass it to `taco batch` instead",
+            id.replace('_', "-")
+        ));
+    }
+
+    Ok(Cli::from_arg_matches(&matches)?)
+}
+
+/// Run a command or group of the current directory, with the execution flags passed to taco. This
+/// returns the exit code of the command, or `1` when it doesn't exist.
+fn run_alias(
+    args: &Cli,
+    exec: &ExecArgs,
+    alias: &str,
+    arguments: &[String],
+    pwd: &str,
+    store: &Store,
+) -> Result<i32> {
+    // The directory to resolve the commands from
+    let resolve_in = match &exec.resolve_as {
+        Some(path) => resolve_pwd(path)?,
+        None => pwd.to_string(),
+    };
+
+    let config = store.read_for(&resolve_in)?;
+    let options = ExecOptions {
+        resolve_as: exec.resolve_as.as_ref().map(|_| resolve_in.clone()),
+        print: exec.print || exec.no_exec,
+        with_cd: exec.with_cd,
+        full: exec.full,
+        yes: args.yes,
+        allow_dirty: exec.allow_dirty,
+        force: exec.force,
+        no_filter: exec.no_filter,
+        progress: exec.progress && progress::is_supported(),
+        json_result: exec.json_result,
+        strict_args: exec.strict_args,
+        bell: exec.bell,
+        background: exec.background,
+        output: exec.output.as_ref().map(File::create).transpose()?,
+        streams: tee::Streams {
+            stdout: !exec.output_stderr,
+            stderr: !exec.output_stdout,
+        },
+    };
+    let project = config.resolve_project(&resolve_in)?;
+    let groups = config.resolve_groups(&resolve_in)?;
+
+    if project.contains_key(alias) {
+        return execute(&config, &project, alias, arguments, pwd, &options);
+    }
+
+    if groups.contains_key(alias) {
+        if !arguments.is_empty() {
+            return Err(eyre!("Groups don't accept passthrough arguments"));
+        }
+
+        return execute_group(&config, &project, &groups, alias, pwd, &options, 0);
+    }
+
+    // Project exists but command doesn't, or there are no commands for this directory at all.
+    // Either way this should fail, so that `taco foo || fallback` works.
+    println!("{}", not_found(alias, &project, &groups));
+    print_project_commands(&project, &groups, &ListOptions::default());
+
+    Ok(1)
+}
+
+/// Explain that a command doesn't exist, with a suggestion in case it was a typo.
+fn not_found(alias: &str, project: &Project, groups: &Groups) -> String {
+    let mut message = format!("Command `{}` does not exist.\n", alias.blue());
+    if let Some(name) = suggest::closest(alias, project.keys().chain(groups.keys())) {
+        message.push_str(&format!("Did you mean `{}`?\n", name.blue()));
+    }
+    message
+}
+
+/// How to execute a command, based on the flags passed to taco.
+struct ExecOptions {
+    /// The directory the commands are resolved from, when it's not the directory they run in.
+    resolve_as: Option<String>,
+    print: bool,
+    with_cd: bool,
+    full: bool,
+    yes: bool,
+    allow_dirty: bool,
+    force: bool,
+    no_filter: bool,
+    progress: bool,
+    json_result: bool,
+    strict_args: bool,
+    bell: bool,
+    background: bool,
+    output: Option<File>,
+    streams: tee::Streams,
+}
+
+/// The exit code of a finished process. A process that was killed by a signal has no exit code,
+/// so it is reported the way shells do, e.g. `130` for `SIGINT`.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+
+    status.code().unwrap_or(1)
+}
+
+/// Execute a command of the resolved project, and return its exit code.
+fn execute(
+    config: &Config,
+    project: &Project,
+    alias: &str,
+    arguments: &[String],
+    pwd: &str,
+    options: &ExecOptions,
+) -> Result<i32> {
+    let mut args = match project.get(alias) {
+        Some(args) => args.clone(),
+        None => return Err(eyre!("Command not found: {}", alias)),
+    };
+
+    // The directory the command runs in, which isn't necessarily where it was invoked
+    let invoked_in = pwd;
+    let resolved_in = options.resolve_as.as_deref().unwrap_or(invoked_in);
+    let pwd = &config.resolve_cwd(resolved_in, invoked_in, alias, &args.options)?;
+
+    // Prefix to reproduce the directory the command runs in
+    let cd = match options.with_cd {
+        true => format!("cd {} && ", shell::quote(pwd)),
+        false => String::new(),
+    };
+
+    if options.print && !options.full {
+        // Actually print the command
+        println!("{}{}", cd, args);
+
+        return Ok(0);
+    }
+
+    let shell = shell::default_shell();
+
+    // Interpret the command as a template
+    let mut uses_args = false;
+    if let Some(TemplateEngine::Minijinja) = config.template_engine {
+        let vars = config.resolve_vars(resolved_in)?;
+        let rendered = template::render(alias, &args.command, arguments, pwd, &vars)?;
+        args.command = rendered.command;
+        uses_args = rendered.uses_args;
+    } else if let Some(command) = shell::substitute_args(&args.command, arguments) {
+        // Placeholders for the passthrough arguments, e.g. `docker logs $1`
+        args.command = command;
+        uses_args = true;
+    }
+
+    // Passthrough arguments, quoted when there is no shell to split them
+    let command = match args.argv {
+        true => arguments
+            .iter()
+            .map(|argument| shell::quote(argument))
+            .collect::<Vec<_>>()
+            .join(" "),
+        false => arguments.join(" "),
+    };
+
+    // Only commands that use the arguments themselves accept them in strict mode
+    let strict_args = args
+        .options
+        .strict_args
+        .unwrap_or(config.strict_args || options.strict_args);
+    if strict_args && !arguments.is_empty() && !uses_args {
+        println!(
+            "{}",
+            format!(
+                "\"{}\" doesn't accept arguments, but got \"{}\".",
+                alias, command
+            )
+            .red()
+        );
+        return Ok(1);
+    }
+
+    // Attach arguments to existing command
+    if !command.is_empty() && !uses_args {
+        args.command.push(' ');
+        args.command.push_str(&command);
+    }
+
+    let resolved = args.command.clone();
+
+    // Prepare the environment of the shell, for the command as well as for its `onfail`
+    let shell_env = config.resolve_shell_env(resolved_in, alias)?;
+    if let (false, Some(env)) = (args.argv, &shell_env) {
+        args.command = format!("{}\n{}", env, args.command);
+    }
+
+    // Add common flags for different shells
+    let flags = shell::shell_args(&shell);
+
+    // Execute the command, possibly as another user
+    let mut cmd = match args.argv {
+        // Nothing is interpreted by a shell, the arguments are passed as is
+        true => {
+            let argv = args.arguments();
+            let Some((program, rest)) = argv.split_first() else {
+                return Err(eyre!("\"{}\" has no arguments to run", alias));
+            };
+
+            let mut cmd = match &args.options.run_as {
+                Some(user) => processes::run_as(user, program, &args.options.env)?,
+                None => Command::new(program),
+            };
+            cmd.args(rest);
+            cmd
+        }
+        false => {
+            let mut cmd = match &args.options.run_as {
+                Some(user) => processes::run_as(user, &shell, &args.options.env)?,
+                None => Command::new(&shell),
+            };
+            cmd.args(&flags);
+            shell::arg(&mut cmd, &shell, &args.command);
+            cmd
+        }
+    };
+    cmd.current_dir(pwd).envs(&args.options.env);
+
+    if options.print {
+        // Print the full invocation, so that it can be reproduced exactly
+        let env = args
+            .options
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, shell::quote(value)));
+        let argv: Vec<String> = env
+            .chain(
+                std::iter::once(cmd.get_program())
+                    .chain(cmd.get_args())
+                    .map(|arg| shell::quote(&arg.to_string_lossy())),
+            )
+            .collect();
+        println!("{}{}", cd, argv.join(" "));
+
+        return Ok(0);
+    }
+
+    // One-time commands that already ran
+    let marker = match &args.options.once {
+        Some(marker) => Some(config.resolve_once_marker(resolved_in, alias, marker)?),
+        None => None,
+    };
+    if let (Some(marker), false) = (&marker, options.force) {
+        if marker.exists() {
+            println!(
+                "{}",
+                format!(
+                    "\"{}\" already ran, remove {} or use --force to run it again.",
+                    alias,
+                    marker.display()
+                )
+                .dimmed()
+            );
+            return Ok(0);
+        }
+    }
+
+    if args.options.requires_clean_tree && !options.allow_dirty {
+        let mut git = Command::new("git");
+        git.args(["status", "--porcelain"]).current_dir(pwd);
+        let status = git.output()?;
+        trace::log(&git, status.status.code());
+
+        // Not a git repository
+        if !status.status.success() {
+            println!(
+                "{}",
+                format!(
+                    "\"{}\" requires a clean git working tree, but {} is not a git repository.",
+                    alias, pwd
+                )
+                .red()
+            );
+            return Ok(1);
+        }
+
+        // Uncommitted changes
+        if !status.stdout.is_empty() {
+            println!(
+                "{}",
+                format!(
+                    "\"{}\" requires a clean git working tree, commit or stash your changes first.",
+                    alias
+                )
+                .red()
+            );
+            return Ok(1);
+        }
+    }
+
+    let definition_dir = config.definition_dir(resolved_in, alias)?;
+    if !options.yes
+        && args
+            .options
+            .needs_confirmation(Path::new(pwd), Path::new(&definition_dir))
+    {
+        // Fill in the actual arguments, so that it's clear what is about to happen
+        let message = match &args.options.confirm_text {
+            Some(text) => {
+                let vars = config.resolve_vars(resolved_in)?;
+                template::render(alias, text, arguments, pwd, &vars)?.command
+            }
+            None => format!(
+                "Are you sure you want to run \"{}\" in {}?",
+                alias.blue(),
+                pwd.dimmed()
+            ),
+        };
+
+        if !confirm(&message) {
+            println!("{}", "Aborted!".red());
+            return Ok(1);
+        }
+    }
+
+    let cmd = &mut cmd;
+
+    if let Some(nice) = args.options.nice {
+        processes::set_priority(cmd, nice)?;
+    }
+
+    if options.background {
+        let detached = processes::spawn_detached(cmd, alias, &logs_dir())?;
+        trace::log(cmd, None);
+
+        let mut registry = processes::read_registry(&processes_file())?;
+        registry.push(detached.clone());
+        processes::write_registry(&processes_file(), &registry)?;
+
+        println!(
+            "Started \"{}\" in the background (PID {})\n  {}",
+            alias.blue(),
+            detached.pid,
+            detached.log.display().to_string().dimmed()
+        );
+        return Ok(0);
+    }
+
+    let filter = args.options.filter.as_ref().filter(|_| !options.no_filter);
+    let started_at = Instant::now();
+
+    let status = match (&options.output, filter) {
+        (Some(output), _) => tee::run(cmd, output.try_clone()?, options.streams)?,
+        (None, Some(filter)) => {
+            let mut filter_cmd = Command::new(&shell);
+            filter_cmd.args(&flags).current_dir(pwd);
+            shell::arg(&mut filter_cmd, &shell, filter);
+
+            tee::run_filtered(cmd, &mut filter_cmd)?
+        }
+        (None, None) if options.progress => progress::run(cmd)?,
+
+        // The output is streamed straight to the terminal, nothing is buffered in between
+        (None, None) => cmd
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .wrap_err_with(|| format!("Failed to run \"{}\"", alias))?,
+    };
+    trace::log(cmd, status.code());
+    let code = exit_code(status);
+
+    let duration = started_at.elapsed();
+
+    // Remember that the command ran, so that it is skipped next time
+    if let (0, Some(marker)) = (code, &marker) {
+        fs::create_dir_all(marker.parent().unwrap())?;
+        File::create(marker)?;
+    }
+
+    // Recover from the failure, but keep the exit code of the original command. The recovery runs
+    // in the same environment and as the same user as the command itself.
+    if let (true, Some(onfail)) = (code != 0, &args.options.onfail) {
+        let onfail = match &shell_env {
+            Some(env) => format!("{}\n{}", env, onfail),
+            None => onfail.clone(),
+        };
+
+        let mut onfail_cmd = match &args.options.run_as {
+            Some(user) => processes::run_as(user, &shell, &args.options.env)?,
+            None => Command::new(&shell),
+        };
+        onfail_cmd.args(&flags);
+        shell::arg(&mut onfail_cmd, &shell, &onfail);
+
+        let status = onfail_cmd
+            .current_dir(pwd)
+            .envs(&args.options.env)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+        trace::log(&onfail_cmd, status.code());
+    }
+
+    // An audible cue for when you switched to another window in the meantime
+    if (args.options.bell || options.bell) && std::io::stderr().is_terminal() {
+        eprint!("\x07");
+    }
+
+    if options.json_result {
+        let result = RunResult {
+            alias: alias.to_string(),
+            command: resolved,
+            cwd: pwd.to_string(),
+            exit_code: code,
+            duration_ms: duration.as_millis(),
+        };
+        eprintln!("{}", serde_json::to_string(&result)?);
+    }
+
+    Ok(code)
+}
+
+/// Groups can contain other groups, but not endlessly.
+const MAX_GROUP_DEPTH: usize = 16;
+
+/// Execute the commands of a group in sequence, and return the exit code of the first command that
+/// failed.
+fn execute_group(
+    config: &Config,
+    project: &Project,
+    groups: &Groups,
+    name: &str,
+    pwd: &str,
+    options: &ExecOptions,
+    depth: usize,
+) -> Result<i32> {
+    if depth > MAX_GROUP_DEPTH {
+        return Err(eyre!(
+            "Group \"{}\" is nested too deeply, does it contain itself?",
+            name
+        ));
+    }
+
+    for member in groups.get(name).into_iter().flatten() {
+        let code = if project.contains_key(member) {
+            execute(config, project, member, &[], pwd, options)?
+        } else if groups.contains_key(member) {
+            execute_group(config, project, groups, member, pwd, options, depth + 1)?
+        } else {
+            return Err(eyre!(
+                "Group \"{}\" refers to a command that does not exist: {}",
+                name,
+                member
+            ));
+        };
+
+        if code != 0 {
+            return Ok(code);
+        }
+    }
+
+    Ok(0)
+}
+
+/// Resolve the `--pwd` to a canonical path. Relative paths are resolved against the current working
+/// directory of the process.
+fn resolve_pwd(pwd: &str) -> Result<String> {
+    resolve_pwd_in(&std::env::current_dir()?, pwd)
+}
+
+fn resolve_pwd_in(base: &Path, pwd: &str) -> Result<String> {
+    let path = base.join(pwd);
+
+    match fs::canonicalize(&path) {
+        Ok(path) if path.is_dir() => Ok(path.to_str().unwrap().to_string()),
+        Ok(path) => Err(eyre!("Not a directory: {}", path.display())),
+        Err(_) => Err(eyre!(
+            "Directory does not exist: {} (resolved to {})",
+            pwd,
+            path.display()
+        )),
+    }
+}
+
+fn print_project_commands(project: &Project, groups: &Groups, list: &ListOptions) {
+    println!("Available commands:\n");
+    let commands = project.len();
+
+    // No commands
+    if commands == 0 {
+        println!("{}", " \u{2219} There are no commands available.\n".red());
+    }
+
+    // Commands
+    for (key, value) in sorted(project, list.sort) {
+        println!(
+            "  taco {}\n    {}\n",
+            key.blue(),
+            format_command(value, list.highlight)
+        );
+    }
+
+    // Groups
+    if !groups.is_empty() {
+        println!("Available groups:\n");
+    }
+
+    for (key, value) in groups {
+        println!(
+            "  taco {}\n    {}\n",
+            key.blue(),
+            value.join(" \u{2192} ").dimmed()
+        );
+    }
+
+    // Footer
+    println!(
+        "{}",
+        format!(
+            "{} command{}",
+            commands,
+            match commands {
+                1 => "",
+                _ => "s",
+            }
+        )
+        .dimmed()
+    );
+}
+
+/// Export the resolved commands of a directory as a shell script, with the directory and environment
+/// they run in.
+fn export_script(config: &Config, path: &str) -> Result<String> {
+    // The script can't fill in the passthrough arguments of a template the way taco does
+    if config.template_engine.is_some() {
+        return Err(eyre!(
+            "Commands can't be exported as a shell script when a template engine is used."
+        ));
+    }
+
+    let mut entries = vec![];
+    for (name, spec) in config.resolve_project(path)? {
+        let strategy = spec
+            .options
+            .cwd_strategy
+            .or(config.cwd_strategy)
+            .unwrap_or_default();
+
+        // Commands that run where they are invoked, run where the script is invoked instead
+        let base = match strategy {
+            CwdStrategy::Invocation => None,
+            CwdStrategy::Definition => Some(shell::quote(&config.resolve_cwd_strategy(
+                path,
+                path,
+                &name,
+                &spec.options,
+            )?)),
+            CwdStrategy::GitRoot => Some("\"$(git rev-parse --show-toplevel)\"".to_string()),
+        };
+        let cwd = match (base, &spec.options.cwd) {
+            (Some(base), Some(cwd)) if !Path::new(cwd).is_absolute() => {
+                Some(format!("{}/{}", base, shell::quote(cwd)))
+            }
+            (_, Some(cwd)) => Some(shell::quote(cwd)),
+            (base, None) => base,
+        };
+
+        let command = match config.resolve_shell_env(path, &name)? {
+            Some(env) => format!("{}\n{}", env, spec.command),
+            None => spec.command,
+        };
+
+        entries.push(script::Entry {
+            name,
+            command,
+            cwd,
+            env: spec.options.env,
+        });
+    }
+
+    Ok(script::render(
+        path,
+        &entries,
+        &config.resolve_groups(path)?,
+    ))
+}
+
+/// Print the available commands, grouped by the project that defines them.
+fn print_project_tree(sources: &[(Source, &Project)], resolved: &Project, list: &ListOptions) {
+    println!("Available commands:\n");
+
+    // No commands
+    if sources.is_empty() {
+        println!("{}", " \u{2219} There are no commands available.\n".red());
+    }
+
+    for (index, (source, project)) in sources.iter().enumerate() {
+        if !project.keys().any(|key| list.includes(key)) {
+            continue;
+        }
+
+        println!("{}\n", source.to_string().bold());
+
+        for (key, value) in sorted(project, list.sort) {
+            if !list.includes(key) {
+                continue;
+            }
+
+            // Commands that are defined again by a later project are not used
+            let overridden = sources[index + 1..]
+                .iter()
+                .any(|(_, later)| later.contains_key(key));
+
+            match overridden {
+                true => println!(
+                    "  {}\n    {}\n",
+                    format!("taco {} (overridden)", key)
+                        .dimmed()
+                        .strikethrough(),
+                    value.command.dimmed()
+                ),
+                false => println!(
+                    "  taco {}\n    {}\n",
+                    key.blue(),
+                    format_command(value, list.highlight)
+                ),
+            }
+        }
+    }
+
+    // Footer
+    println!(
+        "{}",
+        format!(
+            "{} command{}",
+            resolved.len(),
+            match resolved.len() {
+                1 => "",
+                _ => "s",
+            }
+        )
+        .dimmed()
+    );
+}
+
+/// The commands of a project in the order they should be listed in.
+fn sorted(project: &Project, sort: Sort) -> Vec<(&String, &CommandSpec)> {
+    let mut commands: Vec<_> = project.iter().collect();
+
+    match sort {
+        // Projects are already sorted by name
+        Sort::Name => {}
+        Sort::Command => commands.sort_by(|a, z| a.1.command.cmp(&z.1.command)),
+    }
+
+    commands
+}
+
+/// Print the commands that aliasing `target` would add, by comparing the resolved project before
+/// and after adding the alias.
+fn print_alias_preview(target: &str, commands: &Project, before: &Project, after: &Project) {
+    println!("Aliasing \"{}\" would add:\n", target.blue());
+
+    if commands.is_empty() {
+        println!("{}", " \u{2219} There are no commands to add.\n".red());
+    }
+
+    for (key, value) in commands {
+        let note = match (before.get(key), after.get(key)) {
+            // A command of the current directory (or a later alias) still wins
+            (_, Some(resolved)) if resolved != value => {
+                " (shadowed by an existing command)".dimmed().to_string()
+            }
+            (Some(existing), _) if existing == value => " (already available)".dimmed().to_string(),
+            (Some(existing), _) => format!(" (overrides \"{}\")", existing.command)
+                .yellow()
+                .to_string(),
+            (None, _) => String::new(),
+        };
+
+        println!(
+            "  taco {}{}\n    {}\n",
+            key.blue(),
+            note,
+            value.command.dimmed()
+        );
+    }
+
+    println!("{}", "Nothing was changed (dry run)".dimmed());
+}
+
+fn format_command(spec: &CommandSpec, highlight: bool) -> String {
+    match highlight {
+        true => highlight::highlight(&spec.command),
+        false => spec.command.dimmed().to_string(),
+    }
+}
+
+/// The names of the commands to check, either a single command, the commands of a group or all the
+/// commands of the project. Returns `None` when the command doesn't exist.
+fn commands_to_check<'a>(
+    name: &'a Option<String>,
+    all: bool,
+    project: &'a Project,
+    groups: &'a Groups,
+) -> Option<Vec<&'a String>> {
+    match name {
+        _ if all => Some(project.keys().collect()),
+        Some(name) if project.contains_key(name) => Some(vec![name]),
+        Some(name) if groups.contains_key(name) => Some(groups[name].iter().collect()),
+        Some(name) => {
+            println!("Command \"{}\" does not exist.", name.blue());
+            None
+        }
+        None => unreachable!("clap requires a name or --all"),
+    }
+}
+
+/// Let the user pick any number of commands of a project from a list. Returns the names of the
+/// selected commands.
+fn select_commands(prompt: &str, project: &Project) -> Result<Vec<String>> {
+    let names: Vec<&String> = project.keys().collect();
+    let items: Vec<String> = project
+        .iter()
+        .map(|(key, value)| format!("{}  {}", key.blue(), value.command.dimmed()))
+        .collect();
 
-            Ok(())
-        }
-        Some(Commands::Print { json }) => {
-            let mut config = read_config()?;
+    let selection = MultiSelect::new()
+        .with_prompt(prompt)
+        .items(&items)
+        .interact_opt()?
+        .unwrap_or_default();
 
-            if *json {
-                println!(
-                    "{}",
-                    serde_json::to_string_pretty(&config.resolve_project(&pwd)?)?
-                );
-            } else {
-                print_project_commands(&config.resolve_project(&pwd)?)
-            }
+    Ok(selection
+        .into_iter()
+        .map(|index| names[index].to_owned())
+        .collect())
+}
 
-            Ok(())
-        }
-        None => {
-            if args.alias.is_none() {
-                print_help()?;
-            }
+/// The editor of the user, if any. `$VISUAL` takes precedence over `$EDITOR`, like it does for
+/// other tools. It can include arguments, e.g. `code --wait`, which the `Editor` splits like a shell
+/// would before passing the file.
+fn editor() -> Option<String> {
+    editor_from(std::env::var("VISUAL").ok(), std::env::var("EDITOR").ok())
+}
 
-            let mut config = read_config()?;
-            let alias = &args.alias.unwrap();
-            let pwd = &args.pwd;
-            let print = args.print;
-            let arguments = args.arguments;
-            let mut project = config.resolve_project(pwd)?;
+fn editor_from(visual: Option<String>, editor: Option<String>) -> Option<String> {
+    [visual, editor]
+        .into_iter()
+        .flatten()
+        .find(|editor| !editor.trim().is_empty())
+}
 
-            match project.get_mut(alias) {
-                Some(args) if print => {
-                    // Actually print the command
-                    println!("{}", args);
-                }
-                Some(args) => {
-                    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+/// Edit the contents in a temporary file with the given extension, for the syntax highlighting of
+/// the editor. Returns `None` when the editor was closed without saving.
+fn edit_in(editor: &str, extension: &str, contents: &str) -> Result<Option<String>> {
+    Editor::new()
+        .executable(editor)
+        .extension(extension)
+        .edit(contents)
+        .wrap_err_with(|| format!("Failed to edit in {}", editor))
+}
 
-                    // Execute the command
-                    let mut cmd = Command::new(&shell);
-                    cmd.current_dir(pwd);
+/// Edit the commands of a project as TOML in the editor, and replace them with the result when it
+/// is valid. Nothing is changed when the result doesn't parse.
+fn edit_project(editor: &str, pwd: &str, store: &mut Store) -> Result<()> {
+    let mut config = store.read()?;
+    let before = config.projects.get(pwd).cloned().unwrap_or_default();
 
-                    // Passthrough arguments
-                    let command = arguments.join(" ");
+    let contents = format!(
+        "# The commands of {}, as `name = \"command\"`. Commands with options are tables.\n\n{}",
+        pwd,
+        toml::to_string_pretty(&before)?
+    );
+    let Some(edited) = edit_in(editor, ".toml", &contents)? else {
+        println!("{}", "No changes were saved.".dimmed());
+        return Ok(());
+    };
 
-                    // Attach arguments to existing command
-                    if !command.is_empty() {
-                        args.push(' ');
-                        args.push_str(&command);
-                    }
+    let after: Project =
+        toml::from_str(&edited).wrap_err("Failed to parse the commands, nothing was changed")?;
+    if after == before {
+        println!("{}", "No changes were made.".dimmed());
+        return Ok(());
+    }
 
-                    // Add common flags for different shells
-                    let cmd = match shell.as_str() {
-                        "/bin/zsh" => cmd.arg("-i").arg("-c"),
-                        "/bin/sh" => cmd.arg("-c"),
-                        _ => &mut cmd,
-                    };
+    for (name, spec) in &after {
+        match before.get(name) {
+            None => println!("  {} {}", "+".green(), name.blue()),
+            Some(previous) if previous != spec => println!("  {} {}", "~".yellow(), name.blue()),
+            Some(_) => {}
+        }
+    }
+    for name in before.keys().filter(|name| !after.contains_key(*name)) {
+        println!("  {} {}", "-".red(), name.blue());
+    }
 
-                    cmd.arg(args);
-
-                    if let Some(code) = cmd
-                        .stdin(Stdio::inherit())
-                        .stdout(Stdio::inherit())
-                        .stderr(Stdio::inherit())
-                        .output()
-                        .expect("failed to execute process")
-                        .status
-                        .code()
-                    {
-                        std::process::exit(code);
-                    }
-                }
-                None => {
-                    // Project exists but command doesn't.
-                    println!("Command `{}` does not exist.\n", alias.blue());
-                    print_project_commands(&project);
-                }
-            }
+    match after.is_empty() {
+        true => config.projects.remove(pwd),
+        false => config.projects.insert(pwd.to_string(), after),
+    };
+    store.write(&config)?;
+
+    println!("\nUpdated the commands of {}", pwd.dimmed());
+
+    Ok(())
+}
 
-            Ok(())
+/// Merge an imported config into the existing one. The aliases are combined, and the commands,
+/// groups, variables and shell environments are added per project. Settings such as the
+/// `template_engine` are left as they are. Returns the number of values that changed.
+fn import_config(config: &mut Config, imported: Config, overwrite: bool) -> usize {
+    let mut changes = 0;
+
+    // The keys are used verbatim, they are canonical paths already (or predefined projects)
+    for (key, aliases) in imported.aliases {
+        for alias in aliases {
+            let existing = config.aliases.entry(key.clone()).or_default();
+            if !existing.contains(&alias) {
+                println!(
+                    "  {} alias {} in {}",
+                    "+".green(),
+                    alias.blue(),
+                    key.dimmed()
+                );
+                existing.push(alias);
+                changes += 1;
+            }
         }
     }
-}
 
-fn print_project_commands(project: &Project) {
-    println!("Available commands:\n");
-    let commands = project.len();
+    for (key, project) in imported.projects.into_iter().filter(|(_, p)| !p.is_empty()) {
+        let existing = config.projects.entry(key.clone()).or_default();
+        changes += merge_entries(existing, project, overwrite, |name| {
+            format!("{} in {}", name.blue(), key.dimmed())
+        });
+    }
 
-    // No commands
-    if commands == 0 {
-        println!("{}", " \u{2219} There are no commands available.\n".red());
+    for (key, groups) in imported.groups.into_iter().filter(|(_, g)| !g.is_empty()) {
+        let existing = config.groups.entry(key.clone()).or_default();
+        changes += merge_entries(existing, groups, overwrite, |name| {
+            format!("group {} in {}", name.blue(), key.dimmed())
+        });
     }
 
-    // Commands
-    for (key, value) in project {
-        println!("  taco {}\n    {}\n", key.blue(), value.dimmed());
+    for (key, vars) in imported.vars.into_iter().filter(|(_, v)| !v.is_empty()) {
+        let existing = config.vars.entry(key.clone()).or_default();
+        changes += merge_entries(existing, vars, overwrite, |name| {
+            format!("variable {} in {}", name.blue(), key.dimmed())
+        });
     }
 
-    // Footer
-    println!(
-        "{}",
-        format!(
-            "{} command{}",
-            commands,
-            match commands {
-                1 => "",
-                _ => "s",
-            }
-        )
-        .dimmed()
+    changes += merge_entries(
+        &mut config.shell_env,
+        imported.shell_env,
+        overwrite,
+        |key| format!("the shell environment of {}", key.dimmed()),
     );
+
+    changes
+}
+
+/// Add the imported entries that don't exist yet. Entries that exist with a different value are
+/// only replaced when `overwrite` is set, or when the user confirms it. Returns the number of
+/// entries that changed.
+fn merge_entries<V: PartialEq>(
+    existing: &mut BTreeMap<String, V>,
+    imported: BTreeMap<String, V>,
+    overwrite: bool,
+    describe: impl Fn(&str) -> String,
+) -> usize {
+    let mut changes = 0;
+
+    for (name, value) in imported {
+        match existing.get(&name) {
+            Some(current) if *current == value => continue,
+            Some(_) => {
+                let replace = overwrite || confirm(&format!("Replace {}?", describe(&name)));
+                if !replace {
+                    continue;
+                }
+                println!("  {} {}", "~".yellow(), describe(&name));
+            }
+            None => println!("  {} {}", "+".green(), describe(&name)),
+        }
+
+        existing.insert(name, value);
+        changes += 1;
+    }
+
+    changes
 }
 
+/// Ask a yes/no question. Without a terminal to answer it in, e.g. in scripts or CI, the answer is
+/// no instead of waiting for input that never comes.
 fn confirm(message: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        // Still show the question, otherwise it's unclear why nothing happened
+        println!(
+            "{} {}\n",
+            message,
+            "(no, there is no terminal to answer in, use --yes to confirm)".dimmed()
+        );
+        return false;
+    }
+
     let mut s = String::new();
     print!("{} {} ", message, "(y/N)".dimmed());
     let _ = std::io::stdout().flush();
@@ -359,22 +3265,92 @@ fn print_help() -> Result<(), Error> {
         .output()
         .expect("failed to execute process");
 
-    std::process::exit(0);
+    Ok(())
 }
 
 // Currently using a library that automatically gives you the
 // config dir, which does all the magic for you (including the $HOME, $XDG_CONFIG_HOME, ...).
 // However, I'm on MacOS and I also want to use `~/.config`, but it results in
 // `$HOME/Library/Application Support` instead, which sort of makes sense but I don't want that...
-// Therefore doing this manually.
+// Therefore doing this manually, only honoring `$XDG_CONFIG_HOME` when it is explicitly set.
+//
+// The config file can also be pointed at directly with `TACO_CONFIG`, which wins over everything
+// else.
 fn config_file_location() -> String {
-    Path::new(&dirs::home_dir().unwrap())
-        .join(".config")
-        .join("taco")
-        .join("taco.json")
-        .to_str()
+    if let Some(file_path) = std::env::var_os("TACO_CONFIG").filter(|path| !path.is_empty()) {
+        return file_path.to_str().unwrap().to_owned();
+    }
+
+    if is_portable() {
+        return portable_config_file_location();
+    }
+
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&dirs::home_dir().unwrap()).join(".config"),
+    };
+    let dir = config_home.join("taco");
+
+    config_file_in(&dir).to_str().unwrap().to_owned()
+}
+
+/// The config file in the given directory, this is the `taco.toml` if it exists and the
+/// `taco.json` otherwise.
+fn config_file_in(dir: &Path) -> PathBuf {
+    let toml = dir.join("taco.toml");
+
+    match toml.exists() {
+        true => toml,
+        false => dir.join("taco.json"),
+    }
+}
+
+/// Whether the config file is written in TOML, instead of JSON.
+fn is_toml(file_path: &str) -> bool {
+    file_path.ends_with(".toml")
+}
+
+/// Whether taco runs in portable mode, where the config lives next to the executable instead of in
+/// the home directory.
+fn is_portable() -> bool {
+    std::env::var("TACO_PORTABLE").is_ok_and(|value| !matches!(value.as_str(), "" | "0" | "false"))
+}
+
+/// The config file next to the executable, or in the current directory. When neither exists, a
+/// `taco.json` next to the executable is created.
+fn portable_config_file_location() -> String {
+    let next_to_executable = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.to_path_buf()));
+    let dirs: Vec<PathBuf> = [next_to_executable, std::env::current_dir().ok()]
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let location = dirs
+        .iter()
+        .map(|dir| config_file_in(dir))
+        .find(|location| location.exists())
+        .or_else(|| dirs.first().map(|dir| dir.join("taco.json")))
+        .expect("could not determine the location of the portable config");
+
+    location.to_str().unwrap().to_owned()
+}
+
+/// The directory where the output of commands running in the background is written to.
+fn logs_dir() -> PathBuf {
+    Path::new(&config_file_location())
+        .parent()
         .unwrap()
-        .to_owned()
+        .join("logs")
+}
+
+/// The file that keeps track of the commands that are running in the background.
+fn processes_file() -> PathBuf {
+    Path::new(&config_file_location())
+        .parent()
+        .unwrap()
+        .join("processes.json")
 }
 
 fn ensure_config_exists() -> Result<()> {
@@ -395,14 +3371,739 @@ fn ensure_config_exists() -> Result<()> {
 
 fn read_config() -> Result<Config> {
     let file_path = config_file_location();
-    let file = File::open(file_path)?;
-    let config: Config = serde_json::from_reader(file).expect("JSON was not well-formatted");
+    let contents = fs::read_to_string(&file_path)?;
+
+    parse_config(&contents, &file_path)
+}
+
+/// Parse the contents of a config file, in the format that matches its path.
+fn parse_config(contents: &str, file_path: &str) -> Result<Config> {
+    let context = || format!("Failed to parse {}", file_path);
+    let mut config: Config = match is_toml(file_path) {
+        true => toml::from_str(contents).wrap_err_with(context)?,
+        false => serde_json::from_str(contents).wrap_err_with(context)?,
+    };
+    config.map_paths(expand_home);
+
+    Ok(config)
+}
+
+/// The home directory as it appears in the canonical paths of the projects.
+fn home_dir() -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    Some(fs::canonicalize(&home).unwrap_or(home))
+}
+
+/// Expand a path that starts with `~` to the home directory, e.g. `~/projects/app`.
+fn expand_home(path: &str) -> String {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => rest,
+        _ => return path.to_string(),
+    };
+
+    match home_dir() {
+        Some(home) => format!("{}{}", home.display(), rest),
+        None => path.to_string(),
+    }
+}
+
+/// Replace the home directory at the start of a path with `~`, the opposite of `expand_home`.
+fn contract_home(path: &str) -> String {
+    let Some(home) = home_dir() else {
+        return path.to_string();
+    };
+
+    match Path::new(path).strip_prefix(&home) {
+        Ok(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Read only the parts of the config that are needed to resolve the commands of the given
+/// directory. The projects of unrelated directories are skipped without deserializing them, which
+/// keeps the hot path fast for large configs. Don't use this when the config is written again!
+fn read_config_for(pwd: &str) -> Result<Config> {
+    // Skipping the unrelated projects relies on raw JSON values
+    let file_path = config_file_location();
+    let mut config = match is_toml(&file_path) {
+        true => read_config()?,
+
+        // When anything is wrong with the config, parse it in full instead so that the error
+        // points at the right line and column of the file.
+        false => {
+            let contents = fs::read_to_string(file_path)?;
+            filter_config(&contents, pwd).or_else(|_| read_config())?
+        }
+    };
+
+    config.local = read_local_project(Path::new(pwd))?;
+    Ok(config)
+}
+
+/// Find the `.taco.json` in the directory or the closest of its parents, like git finds its
+/// `.git` directory.
+fn read_local_project(pwd: &Path) -> Result<Option<LocalProject>> {
+    let pwd = fs::canonicalize(pwd)?;
+    let Some(dir) = pwd
+        .ancestors()
+        .find(|dir| dir.join(LOCAL_CONFIG_FILE).is_file())
+    else {
+        return Ok(None);
+    };
+
+    let file_path = dir.join(LOCAL_CONFIG_FILE);
+    let contents = fs::read_to_string(&file_path)?;
+    let mut local: LocalProject = serde_json::from_str(&contents)
+        .wrap_err_with(|| format!("Failed to parse {}", file_path.display()))?;
+    local.dir = dir.to_str().unwrap().to_string();
+
+    Ok(Some(local))
+}
+
+fn filter_config(contents: &str, pwd: &str) -> Result<Config> {
+    let mut fields: BTreeMap<String, &RawValue> = serde_json::from_str(contents)?;
+
+    // The aliases are needed to know which projects contribute to the current directory.
+    let mut config = Config::new();
+    if let Some(aliases) = fields.get("aliases") {
+        config.aliases = serde_json::from_str(aliases.get())?;
+        config.map_paths(expand_home);
+    }
+
+    let keys: HashSet<String> = config
+        .resolution_order(&fs::canonicalize(pwd)?)?
+        .into_iter()
+        .map(|source| source.key)
+        .collect();
+
+    let mut filtered: BTreeMap<&str, Box<RawValue>> = BTreeMap::new();
+    for field in ["projects", "groups", "vars"] {
+        if let Some(raw) = fields.remove(field) {
+            let mut projects: BTreeMap<String, &RawValue> = serde_json::from_str(raw.get())?;
+            projects.retain(|key, _| keys.contains(&expand_home(key)));
+            filtered.insert(field, serde_json::value::to_raw_value(&projects)?);
+        }
+    }
+
+    let mut reduced: BTreeMap<&str, &RawValue> = fields
+        .iter()
+        .map(|(key, value)| (key.as_str(), *value))
+        .collect();
+    for (key, value) in &filtered {
+        reduced.insert(key, value);
+    }
+
+    let mut config: Config = serde_json::from_str(&serde_json::to_string(&reduced)?)?;
+    config.map_paths(expand_home);
 
     Ok(config)
 }
 
 fn write_config(config: &Config) -> Result<()> {
     let file_path = config_file_location();
-    std::fs::write(file_path, serde_json::to_string_pretty(&config)?)?;
-    Ok(())
+
+    let config = config.stored();
+
+    // Keep the format the config was written in
+    let contents = match is_toml(&file_path) {
+        true => toml::to_string_pretty(&config)?,
+        false => serde_json::to_string_pretty(&config)?,
+    };
+    write_atomically(&file_path, contents.as_bytes())
+}
+
+/// Write to a temporary file next to the destination first, and then move it into place. A crash
+/// in the middle of writing can then never leave a truncated file behind.
+fn write_atomically(file_path: &str, contents: &[u8]) -> Result<()> {
+    // Write through symlinks, e.g. to a config that lives in a dotfiles repository
+    let target = fs::canonicalize(file_path).unwrap_or_else(|_| PathBuf::from(file_path));
+    let permissions = fs::metadata(&target).map(|metadata| metadata.permissions());
+
+    // Every writer gets its own temporary file, so that concurrent writes can't mix
+    let temporary = target.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        target.file_name().unwrap().to_string_lossy(),
+        std::process::id(),
+        SystemTime::now().duration_since(UNIX_EPOCH)?.subsec_nanos()
+    ));
+
+    let written = fs::write(&temporary, contents)
+        .and_then(|_| match permissions {
+            Ok(permissions) => fs::set_permissions(&temporary, permissions),
+            Err(_) => Ok(()),
+        })
+        .and_then(|_| fs::rename(&temporary, &target));
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+
+    Ok(written?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for a single test, that is removed again when the test is done.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("taco-test-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+
+            // The temporary directory itself can be a symlink, e.g. on macOS
+            TempDir(fs::canonicalize(path).unwrap())
+        }
+
+        fn join(&self, path: &str) -> PathBuf {
+            self.0.join(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn project(commands: &[(&str, &str)]) -> Project {
+        commands
+            .iter()
+            .map(|(name, command)| (name.to_string(), CommandSpec::new(command.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let mut config = Config::new();
+        config
+            .aliases
+            .insert("/projects/app".to_string(), vec!["webdev".to_string()]);
+        config.projects.insert(
+            "webdev".to_string(),
+            BTreeMap::from([("dev".to_string(), CommandSpec::new("next dev".to_string()))]),
+        );
+
+        let mut deploy = CommandSpec::new("./deploy.sh".to_string());
+        deploy.options.dangerous = true;
+        config.projects.insert(
+            "/projects/app".to_string(),
+            BTreeMap::from([("deploy".to_string(), deploy)]),
+        );
+
+        let contents = toml::to_string_pretty(&config).unwrap();
+        let parsed = parse_config(&contents, "taco.toml").unwrap();
+
+        assert_eq!(parsed.aliases, config.aliases);
+        assert_eq!(parsed.projects, config.projects);
+    }
+
+    #[test]
+    fn parse_errors_mention_the_file() {
+        let error =
+            parse_config("{ \"projects\": { ", "/home/robin/.config/taco/taco.json").unwrap_err();
+
+        assert!(format!("{:#}", error).contains("/home/robin/.config/taco/taco.json"));
+    }
+
+    fn aliases(pairs: &[(&str, &str)]) -> Config {
+        let mut config = Config::new();
+        for (project, alias) in pairs {
+            config
+                .aliases
+                .entry(project.to_string())
+                .or_default()
+                .push(alias.to_string());
+        }
+        config
+    }
+
+    #[test]
+    fn aliases_resolve_transitively() {
+        let config = aliases(&[("/projects/app", "webdev"), ("webdev", "node")]);
+        let keys: Vec<String> = config
+            .resolution_order(Path::new("/projects/app"))
+            .unwrap()
+            .into_iter()
+            .map(|source| source.key)
+            .collect();
+
+        assert_eq!(keys, ["/", "/projects", "node", "webdev", "/projects/app"]);
+    }
+
+    #[test]
+    fn aliases_with_a_cycle_are_rejected() {
+        let config = aliases(&[("/projects/app", "a"), ("a", "b"), ("b", "a")]);
+        let error = config
+            .resolution_order(Path::new("/projects/app"))
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Aliases form a cycle: /projects/app \u{2192} a \u{2192} b \u{2192} a"
+        );
+    }
+
+    #[test]
+    fn aliases_that_alias_themselves_are_rejected() {
+        let config = aliases(&[("/projects/app", "a"), ("a", "a")]);
+        let error = config
+            .resolution_order(Path::new("/projects/app"))
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Aliases form a cycle: /projects/app \u{2192} a \u{2192} a"
+        );
+    }
+
+    #[test]
+    fn relative_pwd_is_resolved_against_the_base() {
+        let dir = TempDir::new("relative-pwd");
+        fs::create_dir_all(dir.join("app/sub")).unwrap();
+        fs::create_dir_all(dir.join("sibling")).unwrap();
+        let base = dir.join("app");
+
+        assert_eq!(
+            resolve_pwd_in(&base, "../sibling").unwrap(),
+            dir.join("sibling").to_str().unwrap()
+        );
+        assert_eq!(
+            resolve_pwd_in(&base, "./sub").unwrap(),
+            dir.join("app/sub").to_str().unwrap()
+        );
+
+        let error = resolve_pwd_in(&base, "./missing").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Directory does not exist: ./missing"));
+    }
+
+    #[test]
+    fn environments_fall_back_to_the_base_command() {
+        let dir = TempDir::new("environments");
+        let path = dir.0.to_str().unwrap();
+
+        let mut deploy = CommandSpec::new("./deploy.sh".to_string());
+        deploy.options.environments.insert(
+            "staging".to_string(),
+            "./deploy.sh --host staging.example.com".to_string(),
+        );
+        let mut commands = project(&[("build", "cargo build")]);
+        commands.insert("deploy".to_string(), deploy);
+
+        let mut config = Config::new();
+        config.projects.insert(path.to_string(), commands);
+
+        let command = |environment, name: &str| {
+            config.resolve_project_for(path, environment).unwrap()[name]
+                .command
+                .clone()
+        };
+
+        assert_eq!(
+            command(Some("staging"), "deploy"),
+            "./deploy.sh --host staging.example.com"
+        );
+        assert_eq!(command(Some("staging"), "build"), "cargo build");
+        assert_eq!(command(Some("production"), "deploy"), "./deploy.sh");
+        assert_eq!(command(None, "deploy"), "./deploy.sh");
+    }
+
+    #[test]
+    fn visual_takes_precedence_over_editor() {
+        let some = |value: &str| Some(value.to_string());
+
+        assert_eq!(
+            editor_from(some("code --wait"), some("vi")),
+            some("code --wait")
+        );
+        assert_eq!(editor_from(None, some("vi")), some("vi"));
+        assert_eq!(editor_from(some(" "), some("vi")), some("vi"));
+        assert_eq!(editor_from(some("code --wait"), None), some("code --wait"));
+        assert_eq!(editor_from(None, some("")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn editors_can_have_arguments() {
+        // Writes its first argument into the file it is given
+        let editor = r#"sh -c 'printf "%s" "$0" > "$1"' hello"#;
+
+        assert_eq!(
+            edit_in(editor, ".sh", "").unwrap(),
+            Some("hello".to_string())
+        );
+    }
+
+    #[test]
+    fn local_projects_are_found_from_nested_directories() {
+        let dir = TempDir::new("local-project");
+        fs::create_dir_all(dir.join("repo/src/nested")).unwrap();
+        fs::write(
+            dir.join("repo/.taco.json"),
+            r#"{ "commands": { "dev": "pnpm run dev" } }"#,
+        )
+        .unwrap();
+
+        let local = read_local_project(&dir.join("repo/src/nested"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(local.dir, dir.join("repo").to_str().unwrap());
+        assert_eq!(local.commands["dev"].command, "pnpm run dev");
+
+        assert!(read_local_project(&dir.0).unwrap().is_none());
+    }
+
+    #[test]
+    fn local_projects_win_over_the_config() {
+        let dir = TempDir::new("local-precedence");
+        fs::create_dir_all(dir.join("repo/nested")).unwrap();
+        fs::write(
+            dir.join("repo/.taco.json"),
+            r#"{ "commands": { "dev": "pnpm run dev" } }"#,
+        )
+        .unwrap();
+
+        let nested = dir.join("repo/nested");
+        let mut config = Config::new();
+        config.projects.insert(
+            nested.to_str().unwrap().to_string(),
+            project(&[("dev", "npm run dev"), ("test", "npm test")]),
+        );
+        config.local = read_local_project(&nested).unwrap();
+
+        let resolved = config
+            .resolve_project_for(nested.to_str().unwrap(), None)
+            .unwrap();
+        assert_eq!(resolved["dev"].command, "pnpm run dev");
+        assert_eq!(resolved["test"].command, "npm test");
+    }
+
+    #[test]
+    fn exported_projects_include_their_local_commands() {
+        let dir = TempDir::new("export-local");
+        fs::write(
+            dir.join(".taco.json"),
+            r#"{ "commands": { "dev": "pnpm run dev" } }"#,
+        )
+        .unwrap();
+        let pwd = dir.0.to_str().unwrap();
+
+        // Only defined in the `.taco.json`
+        let mut config = Config::new();
+        config.local = read_local_project(&dir.0).unwrap();
+        let snippet = ProjectSnippet {
+            commands: config.own_commands(pwd).unwrap(),
+            aliases: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&snippet).unwrap(),
+            serde_json::json!({ "commands": { "dev": "pnpm run dev" } })
+        );
+
+        // Merged with the commands of the config, but not with the ones of its parents
+        config.projects.insert(
+            pwd.to_string(),
+            project(&[("dev", "npm run dev"), ("test", "npm test")]),
+        );
+        config.projects.insert(
+            dir.0.parent().unwrap().to_str().unwrap().to_string(),
+            project(&[("parent", "true")]),
+        );
+        let commands = config.own_commands(pwd).unwrap();
+        assert_eq!(commands.keys().collect::<Vec<_>>(), ["dev", "test"]);
+        assert_eq!(commands["dev"].command, "pnpm run dev");
+    }
+
+    /// Run with `cargo test --release -- --ignored filtered_reads`, it compares the time it takes
+    /// to read only the relevant projects against parsing the whole config.
+    #[test]
+    #[ignore]
+    fn filtered_reads_are_faster_for_large_configs() {
+        let dir = TempDir::new("filtered-reads");
+        let pwd = dir.0.to_str().unwrap();
+
+        let mut config = Config::new();
+        for index in 0..2000 {
+            let commands = (0..10)
+                .map(|n| {
+                    (
+                        format!("command-{}", n),
+                        CommandSpec::new(format!("echo {}", n)),
+                    )
+                })
+                .collect();
+            config
+                .projects
+                .insert(format!("/projects/{}", index), commands);
+        }
+        config
+            .projects
+            .insert(pwd.to_string(), project(&[("dev", "npm run dev")]));
+        let contents = serde_json::to_string_pretty(&config).unwrap();
+
+        let time = |read: &dyn Fn() -> Config| {
+            let started_at = Instant::now();
+            for _ in 0..20 {
+                assert!(read()
+                    .resolve_project_for(pwd, None)
+                    .unwrap()
+                    .contains_key("dev"));
+            }
+            started_at.elapsed()
+        };
+        let full = time(&|| parse_config(&contents, "taco.json").unwrap());
+        let filtered = time(&|| filter_config(&contents, pwd).unwrap());
+
+        println!("full: {:?}, filtered: {:?}", full / 20, filtered / 20);
+        assert!(filtered < full);
+    }
+
+    #[test]
+    fn atomic_writes_leave_no_temporary_file_behind() {
+        let dir = TempDir::new("atomic-write");
+        let file_path = dir.join("taco.json");
+        fs::write(&file_path, "{}").unwrap();
+
+        write_atomically(file_path.to_str().unwrap(), b"{ \"aliases\": {} }").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&file_path).unwrap(),
+            "{ \"aliases\": {} }"
+        );
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_writes_keep_symlinks_and_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("atomic-write-symlink");
+        fs::create_dir(dir.join("dotfiles")).unwrap();
+        let target = dir.join("dotfiles/taco.json");
+        fs::write(&target, "{}").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+        let link = dir.join("taco.json");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomically(link.to_str().unwrap(), b"{ \"aliases\": {} }").unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "{ \"aliases\": {} }");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read_dir(dir.join("dotfiles")).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_parents_are_inherited() {
+        let dir = TempDir::new("symlinked-parents");
+        fs::create_dir_all(dir.join("real/inner")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+
+        let key = |path: &str| dir.join(path).to_str().unwrap().to_string();
+        let mut config = Config::new();
+        config
+            .projects
+            .insert(key("real"), project(&[("outer", "echo outer")]));
+        config
+            .projects
+            .insert(key("real/inner"), project(&[("inner", "echo inner")]));
+
+        let resolved = config
+            .resolve_project_for(dir.join("link/inner").to_str().unwrap(), None)
+            .unwrap();
+
+        assert_eq!(resolved.keys().collect::<Vec<_>>(), ["inner", "outer"]);
+    }
+
+    #[test]
+    fn batches_share_one_config_in_memory() {
+        let mut store = Store::Memory {
+            config: Box::new(Config::new()),
+            changed: false,
+        };
+
+        let mut config = store.read().unwrap();
+        config
+            .projects
+            .insert("/projects/app".to_string(), project(&[("build", "make")]));
+        store.write(&config).unwrap();
+
+        let config = store.read().unwrap();
+        assert!(config.projects["/projects/app"].contains_key("build"));
+        assert!(matches!(store, Store::Memory { changed: true, .. }));
+    }
+
+    #[test]
+    fn exec_flags_can_be_passed_before_and_after_run() {
+        for argv in [
+            ["taco", "run", "build", "--print"],
+            ["taco", "--print", "run", "build"],
+        ] {
+            let args = Cli::try_parse_from(argv).unwrap();
+            let Some(Commands::Run { exec, alias, .. }) = &args.command else {
+                panic!("expected `run` for {:?}", argv);
+            };
+
+            assert_eq!(alias, "build");
+            assert!(exec.or(&args.exec).print);
+        }
+    }
+
+    #[test]
+    fn failing_batches_roll_back_the_transaction() {
+        let dir = TempDir::new("batch-transaction");
+        let file = dir.join("batch.txt");
+        fs::write(
+            &file,
+            "add build -- make\nrm missing\nadd test -- make test\n",
+        )
+        .unwrap();
+        let pwd = dir.0.to_str().unwrap();
+        let batch = |transaction: bool| {
+            let mut argv = vec!["taco", "batch", file.to_str().unwrap()];
+            if transaction {
+                argv.push("--transaction");
+            }
+            let mut store = Store::Memory {
+                config: Box::new(Config::new()),
+                changed: false,
+            };
+
+            let code = run(&Cli::try_parse_from(argv).unwrap(), pwd, &mut store).unwrap();
+            (code, store.read().unwrap())
+        };
+
+        // Removing a command that doesn't exist fails, so nothing is written
+        let (code, config) = batch(true);
+        assert_eq!(code, 1);
+        assert!(config.projects.is_empty());
+
+        // Without a transaction, the lines before the failure are kept
+        let (code, config) = batch(false);
+        assert_eq!(code, 1);
+        assert_eq!(config.projects[pwd].keys().collect::<Vec<_>>(), ["build"]);
+    }
+
+    #[test]
+    fn batch_lines_cant_configure_taco_itself() {
+        assert!(parse_batch_line("add build -- make").is_ok());
+        assert!(parse_batch_line("--config other.json add build -- make").is_err());
+        assert!(parse_batch_line("add --portable build -- make").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn zsh_completes_the_commands_when_autoloaded() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Only where zsh is installed
+        if Command::new("zsh").arg("--version").output().is_err() {
+            return;
+        }
+
+        let dir = TempDir::new("zsh-completions");
+        let mut script = vec![];
+        clap_complete::generate(
+            clap_complete::Shell::Zsh,
+            &mut Cli::command(),
+            "taco",
+            &mut script,
+        );
+        script.extend(
+            init::alias_completions(clap_complete::Shell::Zsh)
+                .unwrap()
+                .bytes(),
+        );
+        fs::write(dir.join("_taco"), script).unwrap();
+
+        // `command taco complete-aliases` finds this one first
+        fs::write(dir.join("taco"), "#!/bin/sh\necho build\n").unwrap();
+        fs::set_permissions(dir.join("taco"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        // The first completion runs the body of the autoloaded file, the second one goes through
+        // the function that it registered.
+        let test = r#"
+            fpath=("$1" $fpath)
+            PATH="$1:$PATH"
+            compdef() { registered=$1 }
+            compadd() { print -r -- "$*" }
+            _arguments() { return 1 }
+            autoload -Uz _taco
+            CURRENT=2
+            words=(taco b)
+            _taco
+            $registered
+        "#;
+        let output = Command::new("zsh")
+            .args(["-f", "-c", test, "zsh", dir.0.to_str().unwrap()])
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "-- build\n-- build\n"
+        );
+    }
+
+    #[test]
+    fn renamed_projects_keep_their_settings() {
+        let mut config = aliases(&[("/projects/app", "webdev")]);
+        config
+            .projects
+            .insert("webdev".to_string(), project(&[("dev", "next dev")]));
+        config
+            .shell_env
+            .insert("webdev".to_string(), "nvm use".to_string());
+        config.vars.insert(
+            "webdev".to_string(),
+            BTreeMap::from([("port".to_string(), "3000".to_string())]),
+        );
+
+        assert_eq!(config.rename_project("webdev", "frontend").unwrap(), 1);
+
+        assert_eq!(config.aliases["/projects/app"], ["frontend"]);
+        assert!(config.projects.contains_key("frontend"));
+        assert_eq!(config.shell_env["frontend"], "nvm use");
+        assert!(!config.shell_env.contains_key("webdev"));
+        assert_eq!(config.vars["frontend"]["port"], "3000");
+        assert!(!config.vars.contains_key("webdev"));
+    }
+
+    #[test]
+    fn missing_commands_fail_with_a_suggestion() {
+        let dir = TempDir::new("missing-command");
+        let pwd = dir.0.to_str().unwrap();
+        let mut config = Config::new();
+        config
+            .projects
+            .insert(pwd.to_string(), project(&[("build", "make")]));
+        let store = Store::Memory {
+            config: Box::new(config),
+            changed: false,
+        };
+
+        let args = Cli::try_parse_from(["taco", "biuld"]).unwrap();
+        let code = run_alias(&args, &args.exec, "biuld", &[], pwd, &store).unwrap();
+        assert_eq!(code, 1);
+
+        colored::control::set_override(false);
+        let project = project(&[("build", "make")]);
+        assert_eq!(
+            not_found("biuld", &project, &Groups::new()),
+            "Command `biuld` does not exist.\nDid you mean `build`?\n"
+        );
+        assert_eq!(
+            not_found("deploy", &project, &Groups::new()),
+            "Command `deploy` does not exist.\n"
+        );
+    }
 }
@@ -0,0 +1,333 @@
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A process that was started in the background.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Detached {
+    /// The alias that was used to start the process.
+    pub alias: String,
+
+    /// The process id of the detached process.
+    pub pid: u32,
+
+    /// The file the output of the detached process is written to.
+    pub log: PathBuf,
+
+    /// When the process was started, in seconds since the unix epoch.
+    pub started_at: u64,
+
+    /// When the process was started according to the OS, to tell it apart from a process that
+    /// reuses its PID later on. Only known on Linux.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<u64>,
+}
+
+impl Detached {
+    /// Whether the process is still running. A different process that reuses the PID after this
+    /// one exited doesn't count.
+    pub fn is_running(&self) -> bool {
+        is_running(self.pid)
+            && match self.start_time {
+                Some(recorded) => start_time(self.pid) == Some(recorded),
+                None => true,
+            }
+    }
+
+    /// Ask the process to stop, unless it already exited.
+    pub fn stop(&self) -> Result<()> {
+        match self.is_running() {
+            true => stop(self.pid),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Read the registry of processes that were started in the background.
+pub fn read_registry(file: &Path) -> Result<Vec<Detached>> {
+    if !file.exists() {
+        return Ok(vec![]);
+    }
+
+    Ok(serde_json::from_reader(File::open(file)?)?)
+}
+
+/// Write the registry of processes that were started in the background.
+pub fn write_registry(file: &Path, processes: &[Detached]) -> Result<()> {
+    fs::write(file, serde_json::to_string_pretty(processes)?)?;
+    Ok(())
+}
+
+/// Spawn the command detached from the current terminal. The process runs in its own session and
+/// its output is written to a log file in the `logs_dir`.
+pub fn spawn_detached(cmd: &mut Command, alias: &str, logs_dir: &Path) -> Result<Detached> {
+    fs::create_dir_all(logs_dir)?;
+
+    let started_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let log = logs_dir.join(format!("{}-{}.log", alias, started_at));
+    let stdout = File::create(&log)?;
+    let stderr = stdout.try_clone()?;
+
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::from(stdout))
+        .stderr(Stdio::from(stderr));
+
+    detach(cmd);
+
+    let child = cmd.spawn()?;
+
+    Ok(Detached {
+        alias: alias.to_string(),
+        pid: child.id(),
+        log,
+        started_at,
+        start_time: start_time(child.id()),
+    })
+}
+
+/// Format the time that passed since `timestamp` in a human friendly way, e.g. `5m`.
+pub fn elapsed_since(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(timestamp);
+    let seconds = now.saturating_sub(timestamp);
+
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+/// Run the command with the given scheduling priority, similar to `nice -n`.
+#[cfg(unix)]
+pub fn set_priority(cmd: &mut Command, nice: i32) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    // Only root can lower the nice value, check it upfront instead of failing to start the command
+    let current = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+    if nice < current && unsafe { libc::geteuid() } != 0 {
+        return Err(eyre!(
+            "Running a command with \"nice\": {} requires root, other users can't go below the current nice value of {}.",
+            nice,
+            current
+        ));
+    }
+
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setpriority(libc::PRIO_PROCESS, 0, nice) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn set_priority(_cmd: &mut Command, _nice: i32) -> Result<()> {
+    use colored::Colorize;
+
+    eprintln!(
+        "{}",
+        "Setting the priority of a command is not supported on this platform, ignoring it."
+            .yellow()
+    );
+
+    Ok(())
+}
+
+/// Prepare a command that runs `program` as another user, with the given environment variables.
+/// When taco itself runs as root the user is switched directly, otherwise `sudo` takes care of it
+/// (and of asking for a password).
+#[cfg(unix)]
+pub fn run_as(user: &str, program: &str, env: &BTreeMap<String, String>) -> Result<Command> {
+    use std::os::unix::process::CommandExt;
+
+    if unsafe { libc::geteuid() } != 0 {
+        if Command::new("sudo").arg("-V").output().is_err() {
+            return Err(eyre!(
+                "Running a command as \"{}\" requires `sudo`, or taco running as root.",
+                user
+            ));
+        }
+
+        let mut cmd = Command::new("sudo");
+        cmd.args(sudo_args(user, program, env));
+        return Ok(cmd);
+    }
+
+    let name = std::ffi::CString::new(user)?;
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    if passwd.is_null() {
+        return Err(eyre!("User \"{}\" does not exist.", user));
+    }
+
+    let (uid, gid) = unsafe { ((*passwd).pw_uid, (*passwd).pw_gid) };
+
+    let mut cmd = Command::new(program);
+    cmd.uid(uid).gid(gid).envs(env);
+    Ok(cmd)
+}
+
+/// The arguments to `sudo` to run `program` as another user. sudo resets the environment, so the
+/// variables are set again on the other side of it with `env`.
+#[cfg(unix)]
+fn sudo_args(user: &str, program: &str, env: &BTreeMap<String, String>) -> Vec<String> {
+    let mut args = vec!["-u".to_string(), user.to_string(), "--".to_string()];
+    if !env.is_empty() {
+        args.push("env".to_string());
+        args.extend(env.iter().map(|(key, value)| format!("{}={}", key, value)));
+    }
+    args.push(program.to_string());
+    args
+}
+
+#[cfg(not(unix))]
+pub fn run_as(user: &str, _program: &str, _env: &BTreeMap<String, String>) -> Result<Command> {
+    Err(eyre!(
+        "Running a command as \"{}\" is not supported on this platform.",
+        user
+    ))
+}
+
+/// The time the process started at in clock ticks since boot, from `/proc/{pid}/stat`.
+#[cfg(target_os = "linux")]
+fn start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+
+    // The name in the second field can contain spaces and parentheses, the fields after it can't.
+    // The start time is the 22nd field.
+    let fields = &stat[stat.rfind(')')? + 2..];
+    fields.split(' ').nth(19)?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn start_time(_pid: u32) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // Signal 0 doesn't send anything, but still checks whether the process exists.
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(unix)]
+fn stop(pid: u32) -> Result<()> {
+    // The detached process is the leader of its own session and process group, so signal the
+    // whole group to also stop the processes it spawned.
+    if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGTERM) } == -1
+        && unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == -1
+    {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn is_running(pid: u32) -> bool {
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn stop(pid: u32) -> Result<()> {
+    Command::new("taskkill")
+        .args(["/PID", &pid.to_string(), "/T"])
+        .stdout(Stdio::null())
+        .status()?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn detach(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    // Start a new session so that the process is no longer tied to the terminal, and won't
+    // receive its signals (e.g. when the terminal is closed).
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+}
+
+#[cfg(windows)]
+fn detach(cmd: &mut Command) {
+    use std::os::windows::process::CommandExt;
+
+    const DETACHED_PROCESS: u32 = 0x00000008;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
+
+    cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reused_pids_are_not_running() {
+        let mut process = Detached {
+            alias: "dev".to_string(),
+            pid: std::process::id(),
+            log: PathBuf::from("dev.log"),
+            started_at: 0,
+            start_time: start_time(std::process::id()),
+        };
+        assert!(process.start_time.is_some());
+        assert!(process.is_running());
+
+        // Another process that got the same PID
+        process.start_time = process.start_time.map(|time| time + 1);
+        assert!(!process.is_running());
+        process.stop().unwrap();
+    }
+
+    #[test]
+    fn sudo_keeps_the_environment_of_the_command() {
+        assert_eq!(
+            sudo_args("deploy", "/bin/sh", &BTreeMap::new()),
+            ["-u", "deploy", "--", "/bin/sh"]
+        );
+
+        let env = BTreeMap::from([
+            ("FOO".to_string(), "bar baz".to_string()),
+            ("NODE_ENV".to_string(), "production".to_string()),
+        ]);
+        assert_eq!(
+            sudo_args("deploy", "/bin/sh", &env),
+            [
+                "-u",
+                "deploy",
+                "--",
+                "env",
+                "FOO=bar baz",
+                "NODE_ENV=production",
+                "/bin/sh"
+            ]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use color_eyre::eyre::Result;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a command can be silent before the indicator shows up.
+const DELAY: Duration = Duration::from_secs(2);
+
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+struct Indicator {
+    /// Whether the command is still running without having written any output.
+    silent: bool,
+
+    /// Whether the indicator is currently visible.
+    drawn: bool,
+}
+
+impl Indicator {
+    /// Stop showing the indicator, and remove it from the terminal.
+    fn finish(&mut self) {
+        self.silent = false;
+
+        if self.drawn {
+            eprint!("\r\x1b[2K");
+            self.drawn = false;
+        }
+    }
+}
+
+/// Whether the indicator can be shown, it would only get in the way when stderr isn't a terminal.
+pub fn is_supported() -> bool {
+    io::stderr().is_terminal()
+}
+
+/// Run the command while showing an elapsed time indicator on stderr for as long as the command is
+/// silent. The indicator disappears as soon as the command writes any output or finishes.
+pub fn run(cmd: &mut Command) -> Result<ExitStatus> {
+    let indicator = Arc::new(Mutex::new(Indicator {
+        silent: true,
+        drawn: false,
+    }));
+
+    cmd.stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = cmd.spawn()?;
+
+    let mut handles = vec![];
+    if let Some(stdout) = child.stdout.take() {
+        let indicator = Arc::clone(&indicator);
+        handles.push(thread::spawn(move || copy(stdout, io::stdout(), indicator)));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        let indicator = Arc::clone(&indicator);
+        handles.push(thread::spawn(move || copy(stderr, io::stderr(), indicator)));
+    }
+
+    let spinner = {
+        let indicator = Arc::clone(&indicator);
+        thread::spawn(move || spin(indicator))
+    };
+
+    for handle in handles {
+        handle.join().expect("failed to copy the output")?;
+    }
+
+    let status = child.wait()?;
+
+    indicator.lock().unwrap().finish();
+    spinner
+        .join()
+        .expect("failed to render the progress indicator");
+
+    Ok(status)
+}
+
+fn spin(indicator: Arc<Mutex<Indicator>>) {
+    let started_at = Instant::now();
+
+    for frame in FRAMES.iter().cycle() {
+        thread::sleep(Duration::from_millis(100));
+
+        // Draw while holding the lock, so that the indicator never ends up in between the output of
+        // the command.
+        let mut indicator = indicator.lock().unwrap();
+        if !indicator.silent {
+            return;
+        }
+
+        let elapsed = started_at.elapsed();
+        if elapsed >= DELAY {
+            eprint!("\r{} {}s", frame, elapsed.as_secs());
+            io::stderr().flush().ok();
+            indicator.drawn = true;
+        }
+    }
+}
+
+fn copy(
+    mut from: impl Read,
+    mut to: impl Write,
+    indicator: Arc<Mutex<Indicator>>,
+) -> io::Result<()> {
+    let mut buffer = [0; 8192];
+
+    loop {
+        let read = from.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        indicator.lock().unwrap().finish();
+
+        to.write_all(&buffer[..read])?;
+        to.flush()?;
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{shell, trace};
+use color_eyre::eyre::Result;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The file the hooks append the command lines to, separated by NUL bytes so that commands can span
+/// multiple lines.
+const VARIABLE: &str = "TACO_RECORD_FILE";
+
+/// Sources the user's `.bashrc`, and appends every new history entry to the recording before the
+/// next prompt. Bash has no hook that runs before a command, but the history has the full command
+/// line.
+const BASHRC: &str = r#"[ -f ~/.bashrc ] && . ~/.bashrc
+__taco_record() {
+  local entry
+  entry=$(HISTTIMEFORMAT= builtin history 1)
+  if [ "$entry" != "$__taco_last" ]; then
+    __taco_last=$entry
+    if [[ $entry =~ ^\ *[0-9]+\*?\ \ (.*)$ ]]; then
+      printf '%s\0' "${BASH_REMATCH[1]}" >> "$TACO_RECORD_FILE"
+    fi
+  fi
+}
+__taco_last=$(HISTTIMEFORMAT= builtin history 1)
+PROMPT_COMMAND="__taco_record${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+"#;
+
+/// zsh reads its startup files from `$ZDOTDIR`, which points at the recording directory. Both files
+/// source the user's own file first.
+const ZSHENV: &str = r#"__taco_zdotdir=$ZDOTDIR
+ZDOTDIR=${TACO_ZDOTDIR:-$HOME}
+[ -f "$ZDOTDIR/.zshenv" ] && . "$ZDOTDIR/.zshenv"
+ZDOTDIR=$__taco_zdotdir
+"#;
+
+const ZSHRC: &str = r#"ZDOTDIR=${TACO_ZDOTDIR:-$HOME}
+[ -f "$ZDOTDIR/.zshrc" ] && . "$ZDOTDIR/.zshrc"
+__taco_record() {
+  print -rn -- "$1"$'\0' >> "$TACO_RECORD_FILE"
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec __taco_record
+"#;
+
+const FISH: &str = r#"function __taco_record --on-event fish_preexec
+    printf '%s\0' $argv >> $TACO_RECORD_FILE
+end"#;
+
+/// Start an interactive shell in the given directory, and return the command lines that ran in it
+/// once it exits. Returns `None` for shells that have no hook to record the commands with.
+pub fn session(shell: &str, pwd: &str) -> Result<Option<Vec<String>>> {
+    let dir = std::env::temp_dir().join(format!("taco-record-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+
+    let recorded = record(shell, pwd, &dir);
+    let _ = fs::remove_dir_all(&dir);
+
+    recorded
+}
+
+fn record(shell: &str, pwd: &str, dir: &Path) -> Result<Option<Vec<String>>> {
+    let file = dir.join("commands");
+    fs::write(&file, "")?;
+
+    let mut cmd = Command::new(shell);
+    match shell::name(shell).as_str() {
+        "bash" => {
+            let rcfile = dir.join("bashrc");
+            fs::write(&rcfile, BASHRC)?;
+            cmd.arg("--rcfile").arg(rcfile).arg("-i");
+        }
+        "zsh" => {
+            fs::write(dir.join(".zshenv"), ZSHENV)?;
+            fs::write(dir.join(".zshrc"), ZSHRC)?;
+            let zdotdir = std::env::var_os("ZDOTDIR")
+                .or_else(|| std::env::var_os("HOME"))
+                .unwrap_or_default();
+            cmd.arg("-i")
+                .env("TACO_ZDOTDIR", zdotdir)
+                .env("ZDOTDIR", dir);
+        }
+        "fish" => {
+            cmd.args(["-i", "-C", FISH]);
+        }
+        _ => return Ok(None),
+    }
+
+    let status = cmd
+        .current_dir(pwd)
+        .env(VARIABLE, &file)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()?;
+    trace::log(&cmd, status.code());
+
+    Ok(Some(commands(&fs::read_to_string(file)?)))
+}
+
+/// The command lines of a recording, without the ones that leave the shell.
+fn commands(recorded: &str) -> Vec<String> {
+    recorded
+        .split('\0')
+        .map(str::trim)
+        .filter(|command| !command.is_empty() && !matches!(*command, "exit" | "logout"))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_separated_by_nul_bytes() {
+        assert_eq!(
+            commands("ls -lah\0for f in *; do\n  echo $f\ndone\0exit\0"),
+            ["ls -lah", "for f in *; do\n  echo $f\ndone"]
+        );
+        assert!(commands("").is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bash_sessions_are_recorded() {
+        let dir = std::env::temp_dir().join(format!("taco-test-record-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // Feed the interactive shell from a pipe instead of a terminal
+        let file = dir.join("commands");
+        fs::write(&file, "").unwrap();
+        fs::write(dir.join("bashrc"), BASHRC).unwrap();
+        fs::write(dir.join("input"), "echo one\nls -lah\nexit\n").unwrap();
+        let status = Command::new("bash")
+            .arg("--rcfile")
+            .arg(dir.join("bashrc"))
+            .arg("-i")
+            .env(VARIABLE, &file)
+            .env("HOME", &dir)
+            .env("HISTFILE", dir.join("history"))
+            .stdin(fs::File::open(dir.join("input")).unwrap())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        let recorded = commands(&fs::read_to_string(&file).unwrap());
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(status.success());
+        assert_eq!(recorded, ["echo one", "ls -lah"]);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::shell;
+use std::collections::BTreeMap;
+
+/// A command as it should run from the generated script.
+pub struct Entry {
+    pub name: String,
+
+    /// The command, including the shell environment it needs.
+    pub command: String,
+
+    /// The directory to change into before running the command, as a shell word so that it can be
+    /// determined when the script runs. Relative paths are resolved against the directory the script
+    /// runs in.
+    pub cwd: Option<String>,
+    pub env: BTreeMap<String, String>,
+}
+
+/// Render the commands as a self-contained POSIX shell script, that runs the command named by its
+/// first argument and passes the remaining arguments along. Groups run their members by invoking
+/// the script again.
+pub fn render(project: &str, entries: &[Entry], groups: &BTreeMap<String, Vec<String>>) -> String {
+    let mut out = vec![
+        "#!/bin/sh".to_string(),
+        format!("# The commands of {}, exported by taco.", project),
+        String::new(),
+        "usage() {".to_string(),
+        "  echo \"Usage: $0 <command> [arguments...]\"".to_string(),
+        "  echo".to_string(),
+        "  echo \"Commands:\"".to_string(),
+    ];
+
+    let names = entries.iter().map(|entry| &entry.name).chain(groups.keys());
+    for name in names {
+        out.push(format!("  echo {}", shell::quote(&format!("  {}", name))));
+    }
+
+    out.extend([
+        "}".to_string(),
+        String::new(),
+        "name=\"$1\"".to_string(),
+        "[ $# -gt 0 ] && shift".to_string(),
+        String::new(),
+        "case \"$name\" in".to_string(),
+    ]);
+
+    for entry in entries {
+        out.push(format!("  {})", shell::quote(&entry.name)));
+
+        if let Some(cwd) = &entry.cwd {
+            out.push(format!("    cd {} || exit 1", cwd));
+        }
+
+        // The passthrough arguments are appended to the command like taco does, unless the command
+        // has placeholders for them which the shell fills in itself
+        let command = match shell::substitute_args(&entry.command, &[]) {
+            Some(_) => entry.command.clone(),
+            None => format!("{} \"$@\"", entry.command),
+        };
+        let env = entry
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={} ", key, shell::quote(value)))
+            .collect::<String>();
+        out.push(format!(
+            "    {}sh -c {} sh \"$@\"",
+            env,
+            shell::quote(&command)
+        ));
+        out.push("    ;;".to_string());
+    }
+
+    for (name, members) in groups {
+        let run = members
+            .iter()
+            .map(|member| format!("\"$0\" {}", shell::quote(member)))
+            .collect::<Vec<_>>()
+            .join(" && ");
+
+        out.push(format!("  {})", shell::quote(name)));
+        out.push(format!("    {}", run));
+        out.push("    ;;".to_string());
+    }
+
+    out.extend([
+        "  \"\" | -h | --help)".to_string(),
+        "    usage".to_string(),
+        "    ;;".to_string(),
+        "  *)".to_string(),
+        "    echo \"Command not found: $name\" >&2".to_string(),
+        "    usage >&2".to_string(),
+        "    exit 1".to_string(),
+        "    ;;".to_string(),
+        "esac".to_string(),
+    ]);
+
+    out.join("\n")
+}
@@ -0,0 +1,403 @@
+use crate::trace;
+use color_eyre::eyre::{eyre, Result};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Quote a value so that it is interpreted as a single word by a POSIX shell.
+pub fn quote(value: &str) -> String {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c));
+
+    match safe {
+        true => value.to_string(),
+        false => format!("'{}'", value.replace('\'', "'\\''")),
+    }
+}
+
+/// Split a line into words the way a POSIX shell would, respecting single quotes, double quotes and
+/// backslash escapes. Variables and globs are not expanded.
+pub fn split(line: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(eyre!("Unterminated single quote in: {}", line)),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(eyre!("Unterminated double quote in: {}", line)),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(eyre!("Unterminated double quote in: {}", line)),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_with(String::new).push(c);
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    Ok(words)
+}
+
+/// Substitute the `$1`, `${1}`, `$@` and `$*` placeholders in a command with the passthrough
+/// arguments, the same way the arguments would otherwise be appended. Placeholders in single quotes
+/// are left alone, e.g. in `awk '{ print $1 }'`. Returns `None` when the command has no
+/// placeholders.
+pub fn substitute_args(command: &str, arguments: &[String]) -> Option<String> {
+    let mut out = String::new();
+    let mut used = false;
+    let mut double_quoted = false;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                out.push(c);
+                out.extend(chars.next());
+            }
+            '\'' if !double_quoted => {
+                out.push(c);
+                for c in chars.by_ref() {
+                    out.push(c);
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                double_quoted = !double_quoted;
+                out.push(c);
+            }
+            '$' => {
+                let index = match chars.peek() {
+                    Some('@' | '*') => {
+                        chars.next();
+                        out.push_str(&arguments.join(" "));
+                        used = true;
+                        continue;
+                    }
+
+                    // Like in a shell, `$10` is the first argument followed by a `0`
+                    Some(digit @ '1'..='9') => {
+                        let index = digit.to_digit(10);
+                        chars.next();
+                        index
+                    }
+                    Some('{') => {
+                        let mut ahead = chars.clone();
+                        ahead.next();
+
+                        let mut digits = String::new();
+                        while let Some(digit) = ahead.next_if(char::is_ascii_digit) {
+                            digits.push(digit);
+                        }
+
+                        match (digits.parse::<u32>(), ahead.next()) {
+                            (Ok(index), Some('}')) if index > 0 => {
+                                chars = ahead;
+                                Some(index)
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+
+                match index {
+                    Some(index) => {
+                        let argument = arguments.get(index as usize - 1);
+                        out.push_str(argument.map(String::as_str).unwrap_or_default());
+                        used = true;
+                    }
+                    None => out.push(c),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    used.then_some(out)
+}
+
+/// The shell to run commands with. This is the `$SHELL` of the user, on Windows it falls back to
+/// `%ComSpec%` (usually `cmd.exe`) because `$SHELL` is typically not set there.
+pub fn default_shell() -> String {
+    shell_for(
+        cfg!(windows),
+        env::var("SHELL").ok(),
+        env::var("ComSpec").ok(),
+    )
+}
+
+/// The shell to run commands with on the given platform, based on `$SHELL` and `%ComSpec%`.
+fn shell_for(windows: bool, shell: Option<String>, comspec: Option<String>) -> String {
+    if let Some(shell) = shell {
+        return shell;
+    }
+
+    match windows {
+        true => comspec.unwrap_or_else(|| "cmd.exe".to_string()),
+        false => "/bin/sh".to_string(),
+    }
+}
+
+/// The name of a shell without its directory and extension, e.g. `zsh` for `/usr/bin/zsh` or `cmd`
+/// for `C:\Windows\system32\cmd.exe`.
+pub fn name(shell: &str) -> String {
+    // Windows shells are usually referred to by their full path and extension. Both separators
+    // are accepted on every platform, so that the same config works in WSL as well.
+    let name = shell.rsplit(['/', '\\']).next().unwrap_or(shell);
+    Path::new(name)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or(name)
+        .to_lowercase()
+}
+
+/// The arguments to pass to the shell, so that it runs the command that follows. The shell is
+/// recognized by its file name, regardless of the directory it lives in.
+pub fn shell_args(shell: &str) -> Vec<String> {
+    let args: &[&str] = match name(shell).as_str() {
+        // Interactive, so that the aliases and functions of the user's `.zshrc` are available
+        "zsh" => &["-i", "-c"],
+
+        // Interactive, so that the `status is-interactive` block of the user's `config.fish` runs,
+        // which is where most functions are set up
+        "fish" => &["-i", "-c"],
+        "bash" | "sh" => &["-c"],
+        "cmd" => &["/C"],
+        "powershell" | "pwsh" => &["-NoProfile", "-Command"],
+
+        // Every POSIX shell understands `-c`
+        _ => &["-c"],
+    };
+
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Pass the command to the shell. `cmd.exe` doesn't unescape its arguments the way other programs
+/// do, so the command is passed to it as is.
+#[cfg(windows)]
+pub fn arg(cmd: &mut Command, shell: &str, command: &str) {
+    use std::os::windows::process::CommandExt;
+
+    match shell_args(shell).iter().any(|arg| arg == "/C") {
+        true => cmd.raw_arg(command),
+        false => cmd.arg(command),
+    };
+}
+
+#[cfg(not(windows))]
+pub fn arg(cmd: &mut Command, _shell: &str, command: &str) {
+    cmd.arg(command);
+}
+
+/// Parse the command with the shell without running it. Returns the error of the shell when the
+/// syntax is invalid, which includes the line it occurred on.
+pub fn check_syntax(shell: &str, command: &str) -> Result<Option<String>> {
+    let mut cmd = Command::new(shell);
+    cmd.args(["-n", "-c", command]);
+    let output = cmd.output()?;
+    trace::log(&cmd, output.status.code());
+
+    Ok(match output.status.success() {
+        true => None,
+        false => Some(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    })
+}
+
+/// Shell builtins and keywords that can be in command position without being on the `PATH`.
+const BUILTINS: &[&str] = &[
+    ".", ":", "[", "alias", "bg", "builtin", "case", "cd", "command", "echo", "eval", "exec",
+    "exit", "export", "false", "fg", "for", "if", "jobs", "kill", "printf", "pwd", "read", "set",
+    "shift", "source", "test", "time", "trap", "true", "type", "ulimit", "umask", "unset", "wait",
+    "while",
+];
+
+/// The executable a command starts with, skipping environment variable assignments in front of it,
+/// e.g. `jest` for `NODE_ENV=test jest --watch`.
+pub fn executable(command: &str) -> Option<&str> {
+    command.split_whitespace().find(|word| !is_assignment(word))
+}
+
+/// Where the executable can be found. Paths are resolved relative to `pwd`, names are looked up on
+/// the `PATH`. Builtins are found in the shell itself.
+pub fn find_executable(executable: &str, pwd: &Path) -> Option<PathBuf> {
+    if BUILTINS.contains(&executable) {
+        return Some(PathBuf::from("builtin"));
+    }
+
+    if executable.contains('/') {
+        let path = pwd.join(executable);
+        return path.exists().then_some(path);
+    }
+
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(executable))
+        .find(|path| path.is_file())
+}
+
+/// Environment variable assignments in front of a command, e.g. `NODE_ENV=test`.
+pub fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+/// Whether the name matches a shell style glob, where `*` matches any number of characters and `?`
+/// matches a single character.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match (pattern.first(), name.first()) {
+            (None, None) => true,
+            (Some('*'), _) => {
+                matches(&pattern[1..], name) || (!name.is_empty() && matches(pattern, &name[1..]))
+            }
+            (Some('?'), Some(_)) => matches(&pattern[1..], &name[1..]),
+            (Some(a), Some(b)) if a == b => matches(&pattern[1..], &name[1..]),
+            _ => false,
+        }
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    matches(&pattern, &name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_args_by_file_name() {
+        assert_eq!(shell_args("/usr/bin/zsh"), ["-i", "-c"]);
+        assert_eq!(shell_args("/bin/bash"), ["-c"]);
+        assert_eq!(shell_args("/usr/local/bin/fish"), ["-i", "-c"]);
+        assert_eq!(shell_args("sh"), ["-c"]);
+        assert_eq!(shell_args("cmd.exe"), ["/C"]);
+        assert_eq!(shell_args("pwsh"), ["-NoProfile", "-Command"]);
+    }
+
+    #[test]
+    fn windows_shells() {
+        let comspec = Some(r"C:\Windows\system32\cmd.exe".to_string());
+        let shell = shell_for(true, None, comspec);
+        assert_eq!(shell, r"C:\Windows\system32\cmd.exe");
+        assert_eq!(shell_args(&shell), ["/C"]);
+
+        let shell = shell_for(true, None, None);
+        assert_eq!(shell, "cmd.exe");
+        assert_eq!(shell_args(&shell), ["/C"]);
+
+        // `$SHELL` wins, e.g. in Git Bash
+        let bash = Some(r"C:\Program Files\Git\bin\bash.exe".to_string());
+        let shell = shell_for(true, bash, Some("cmd.exe".to_string()));
+        assert_eq!(shell_args(&shell), ["-c"]);
+
+        let pwsh = r"C:\Program Files\PowerShell\7\pwsh.exe";
+        assert_eq!(shell_args(pwsh), ["-NoProfile", "-Command"]);
+    }
+
+    #[test]
+    fn unix_shells() {
+        assert_eq!(
+            shell_for(false, None, Some("cmd.exe".to_string())),
+            "/bin/sh"
+        );
+        assert_eq!(
+            shell_for(false, Some("/usr/bin/zsh".to_string()), None),
+            "/usr/bin/zsh"
+        );
+    }
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn substitute_args_without_placeholders() {
+        assert_eq!(substitute_args("docker ps", &args(&["-a"])), None);
+    }
+
+    #[test]
+    fn substitute_args_mixed_with_trailing_arguments() {
+        assert_eq!(
+            substitute_args("docker logs $1 --tail ${2}", &args(&["web", "100", "-f"])),
+            Some("docker logs web --tail 100".to_string())
+        );
+        assert_eq!(
+            substitute_args("echo $1 then $@", &args(&["a", "b"])),
+            Some("echo a then a b".to_string())
+        );
+        assert_eq!(
+            substitute_args("echo $2", &args(&["a"])),
+            Some("echo ".to_string())
+        );
+    }
+
+    #[test]
+    fn substitute_args_with_multiple_digits() {
+        let arguments = args(&["1", "2", "3", "4", "5", "6", "7", "8", "9", "10"]);
+
+        assert_eq!(
+            substitute_args("echo ${10}", &arguments),
+            Some("echo 10".to_string())
+        );
+
+        // Like in a shell, `$10` is `$1` followed by a `0`
+        assert_eq!(
+            substitute_args("echo $10", &arguments),
+            Some("echo 10".to_string())
+        );
+        assert_eq!(
+            substitute_args("echo $10", &args(&["a"])),
+            Some("echo a0".to_string())
+        );
+    }
+
+    #[test]
+    fn substitute_args_skips_single_quotes() {
+        assert_eq!(substitute_args("awk '{ print $1 }'", &args(&["a"])), None);
+        assert_eq!(
+            substitute_args("awk '{ print $1 }' $1", &args(&["file"])),
+            Some("awk '{ print $1 }' file".to_string())
+        );
+        assert_eq!(
+            substitute_args("echo \"$1\" \\$2 $0", &args(&["a", "b"])),
+            Some("echo \"a\" \\$2 $0".to_string())
+        );
+    }
+}
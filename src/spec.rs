@@ -0,0 +1,264 @@
+use crate::shell;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A single command of a project. In the config it is either stored as a plain command string (or
+/// array of arguments), or as an object with the command and any additional options.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredCommand", into = "StoredCommand")]
+pub struct CommandSpec {
+    /// The actual command + arguments to run.
+    pub command: String,
+
+    /// Additional options for running the command.
+    pub options: CommandOptions,
+
+    /// Whether the command is stored as an array of arguments, e.g. `["docker", "compose", "up"]`.
+    /// It then runs without a shell, and `command` holds the arguments quoted as a single line.
+    pub argv: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandOptions {
+    /// The scheduling priority to run the command with, similar to `nice -n`. Only supported on
+    /// Unix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+
+    /// Ask for confirmation before running the command.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dangerous: bool,
+
+    /// Directories in which a dangerous command can run without asking for confirmation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub safe_paths: Vec<String>,
+
+    /// The question to ask before running a dangerous command, rendered as a minijinja template
+    /// with the same variables as commands, e.g. `Deploy {{ args[0] }} to production?`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_text: Option<String>,
+
+    /// A command to run when the command fails, e.g. to clean up or to send a notification.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub onfail: Option<String>,
+
+    /// Only run the command when the git working tree has no uncommitted changes.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub requires_clean_tree: bool,
+
+    /// Run the command as another user, similar to `sudo -u`. Only supported on Unix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_as: Option<String>,
+
+    /// Environment variables to run the command with, captured when the command was added.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+
+    /// A command the stdout of the command is piped through, e.g. `grep -E 'warning|error'`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+
+    /// Variants of the command per environment, the one matching `TACO_ENV` replaces the command.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub environments: BTreeMap<String, String>,
+
+    /// Ring the terminal bell when the command finished.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bell: bool,
+
+    /// Reject passthrough arguments when the command doesn't use them in its template, defaults to
+    /// the `strict_args` of the config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict_args: Option<bool>,
+
+    /// The directory to run the command in, defaults to the `cwd_strategy` of the config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd_strategy: Option<CwdStrategy>,
+
+    /// The directory to run the command in, relative to the directory of the `cwd_strategy`, e.g.
+    /// `./frontend`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+
+    /// Only run the command when this marker file doesn't exist yet, it is created when the
+    /// command succeeds. Relative to the directory of the project that defines the command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub once: Option<String>,
+}
+
+/// Which directory a command runs in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CwdStrategy {
+    /// The directory taco was invoked in.
+    #[default]
+    Invocation,
+
+    /// The directory of the project that defines the command.
+    Definition,
+
+    /// The root of the git repository taco was invoked in.
+    GitRoot,
+}
+
+impl CommandSpec {
+    pub fn new(command: String) -> Self {
+        CommandSpec {
+            command,
+            options: CommandOptions::default(),
+            argv: false,
+        }
+    }
+
+    /// The arguments of a command that runs without a shell.
+    pub fn arguments(&self) -> Vec<String> {
+        shell::split(&self.command).unwrap_or_else(|_| vec![self.command.clone()])
+    }
+}
+
+impl CommandOptions {
+    /// Whether running the command in the given directory needs confirmation. Relative safe paths
+    /// are relative to `base`, the directory of the project that defines the command.
+    pub fn needs_confirmation(&self, pwd: &Path, base: &Path) -> bool {
+        self.dangerous
+            && !self.safe_paths.iter().any(|safe| {
+                let safe = base.join(safe);
+                let safe = fs::canonicalize(&safe).unwrap_or(safe);
+                pwd.starts_with(safe)
+            })
+    }
+}
+
+impl fmt::Display for CommandSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.command)
+    }
+}
+
+/// The representation of a command in the config file. Commands without any options are stored as
+/// plain strings to keep the config readable.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredCommand {
+    Plain(StoredValue),
+    Detailed {
+        command: StoredValue,
+
+        #[serde(flatten)]
+        options: Box<CommandOptions>,
+    },
+}
+
+/// A command that runs in the shell, or the arguments of a command that runs without one.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredValue {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+impl From<StoredValue> for CommandSpec {
+    fn from(value: StoredValue) -> Self {
+        match value {
+            StoredValue::Shell(command) => CommandSpec::new(command),
+            StoredValue::Argv(arguments) => CommandSpec {
+                command: arguments
+                    .iter()
+                    .map(|argument| shell::quote(argument))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                options: CommandOptions::default(),
+                argv: true,
+            },
+        }
+    }
+}
+
+impl From<StoredCommand> for CommandSpec {
+    fn from(stored: StoredCommand) -> Self {
+        match stored {
+            StoredCommand::Plain(value) => CommandSpec::from(value),
+            StoredCommand::Detailed { command, options } => CommandSpec {
+                options: *options,
+                ..CommandSpec::from(command)
+            },
+        }
+    }
+}
+
+impl From<CommandSpec> for StoredCommand {
+    fn from(spec: CommandSpec) -> Self {
+        let value = match spec.argv {
+            true => StoredValue::Argv(spec.arguments()),
+            false => StoredValue::Shell(spec.command),
+        };
+
+        match spec.options == CommandOptions::default() {
+            true => StoredCommand::Plain(value),
+            false => StoredCommand::Detailed {
+                command: value,
+                options: Box::new(spec.options),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_safe_paths_are_relative_to_the_project() {
+        let mut options = CommandOptions {
+            dangerous: true,
+            safe_paths: vec!["sandbox".to_string(), "/srv/scratch".to_string()],
+            ..CommandOptions::default()
+        };
+        let base = Path::new("/projects/app");
+
+        assert!(!options.needs_confirmation(Path::new("/projects/app/sandbox/nested"), base));
+        assert!(!options.needs_confirmation(Path::new("/srv/scratch"), base));
+        assert!(options.needs_confirmation(Path::new("/projects/app"), base));
+        assert!(options.needs_confirmation(Path::new("/elsewhere/sandbox"), base));
+
+        options.dangerous = false;
+        assert!(!options.needs_confirmation(Path::new("/projects/app"), base));
+    }
+
+    #[test]
+    fn plain_commands_run_in_the_shell() {
+        let spec: CommandSpec = serde_json::from_str(r#""echo $HOME""#).unwrap();
+
+        assert_eq!(spec, CommandSpec::new("echo $HOME".to_string()));
+        assert_eq!(serde_json::to_string(&spec).unwrap(), r#""echo $HOME""#);
+    }
+
+    #[test]
+    fn arrays_are_passed_as_is() {
+        let json = r#"["echo","$HOME","a b","it's"]"#;
+        let spec: CommandSpec = serde_json::from_str(json).unwrap();
+
+        assert!(spec.argv);
+        assert_eq!(spec.command, r#"echo '$HOME' 'a b' 'it'\''s'"#);
+
+        // Nothing is expanded or split, unlike in a shell
+        assert_eq!(spec.arguments(), ["echo", "$HOME", "a b", "it's"]);
+
+        assert_eq!(serde_json::to_string(&spec).unwrap(), json);
+    }
+
+    #[test]
+    fn arrays_with_options() {
+        let json = r#"{"command":["docker","compose","up"],"dangerous":true}"#;
+        let spec: CommandSpec = serde_json::from_str(json).unwrap();
+
+        assert!(spec.argv);
+        assert!(spec.options.dangerous);
+        assert_eq!(spec.arguments(), ["docker", "compose", "up"]);
+
+        assert_eq!(serde_json::to_string(&spec).unwrap(), json);
+    }
+}
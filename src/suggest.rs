@@ -0,0 +1,61 @@
+/// How many edits a name can be away from what was typed to still be suggested.
+const MAX_DISTANCE: usize = 2;
+
+/// The name that is closest to what was typed, e.g. `build` for `biuld`. Returns `None` when none of
+/// the names are close enough to be a likely typo.
+pub fn closest<'a>(typed: &str, names: impl IntoIterator<Item = &'a String>) -> Option<&'a str> {
+    names
+        .into_iter()
+        .map(|name| (distance(typed, name), name))
+        // Replacing every character of a short name isn't a typo anymore
+        .filter(|(distance, _)| *distance <= MAX_DISTANCE && *distance < typed.chars().count())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name.as_str())
+}
+
+/// The Levenshtein distance between two strings, the number of insertions, deletions and
+/// substitutions it takes to turn one into the other.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+
+    // The distances from the part of `a` seen so far to every prefix of `b`
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_counts_the_edits() {
+        assert_eq!(distance("build", "build"), 0);
+        assert_eq!(distance("buld", "build"), 1);
+        assert_eq!(distance("builds", "build"), 1);
+        assert_eq!(distance("bwild", "build"), 1);
+        assert_eq!(distance("biuld", "build"), 2);
+        assert_eq!(distance("", "dev"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn closest_only_suggests_likely_typos() {
+        let names = ["build".to_string(), "dev".to_string(), "a".to_string()];
+
+        assert_eq!(closest("biuld", &names), Some("build"));
+        assert_eq!(closest("dve", &names), Some("dev"));
+        assert_eq!(closest("deploy", &names), None);
+
+        // Every character would have to change
+        assert_eq!(closest("x", &names), None);
+    }
+}
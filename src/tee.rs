@@ -0,0 +1,85 @@
+use crate::trace;
+use color_eyre::eyre::Result;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Which output streams of a command to copy to a file.
+#[derive(Clone, Copy)]
+pub struct Streams {
+    pub stdout: bool,
+    pub stderr: bool,
+}
+
+/// Run the command while copying its output to the terminal *and* to the given file.
+pub fn run(cmd: &mut Command, file: File, streams: Streams) -> Result<ExitStatus> {
+    let file = Arc::new(Mutex::new(file));
+
+    cmd.stdin(Stdio::inherit())
+        .stdout(match streams.stdout {
+            true => Stdio::piped(),
+            false => Stdio::inherit(),
+        })
+        .stderr(match streams.stderr {
+            true => Stdio::piped(),
+            false => Stdio::inherit(),
+        });
+
+    let mut child = cmd.spawn()?;
+
+    let mut handles = vec![];
+    if let Some(stdout) = child.stdout.take() {
+        let file = Arc::clone(&file);
+        handles.push(thread::spawn(move || copy(stdout, io::stdout(), file)));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        let file = Arc::clone(&file);
+        handles.push(thread::spawn(move || copy(stderr, io::stderr(), file)));
+    }
+
+    for handle in handles {
+        handle.join().expect("failed to copy the output")?;
+    }
+
+    Ok(child.wait()?)
+}
+
+/// Run the command while piping its stdout through the `filter` command. The exit status is the one
+/// of the command itself, not of the filter.
+pub fn run_filtered(cmd: &mut Command, filter: &mut Command) -> Result<ExitStatus> {
+    let mut child = cmd
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+
+    let mut filter_child = filter
+        .stdin(Stdio::from(stdout))
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    let status = child.wait()?;
+    let filter_status = filter_child.wait()?;
+    trace::log(filter, filter_status.code());
+
+    Ok(status)
+}
+
+fn copy(mut from: impl Read, mut to: impl Write, file: Arc<Mutex<File>>) -> io::Result<()> {
+    let mut buffer = [0; 8192];
+
+    loop {
+        let read = from.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        to.write_all(&buffer[..read])?;
+        to.flush()?;
+        file.lock().unwrap().write_all(&buffer[..read])?;
+    }
+}
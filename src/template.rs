@@ -0,0 +1,105 @@
+use color_eyre::eyre::{eyre, Result};
+use minijinja::{context, Environment, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The engine used to interpret command values as templates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateEngine {
+    Minijinja,
+}
+
+/// The result of rendering a command through the template engine.
+pub struct Rendered {
+    /// The rendered command, ready to be executed.
+    pub command: String,
+
+    /// Whether the template references the passthrough `args` itself. If it does, the arguments
+    /// should not be appended to the command anymore.
+    pub uses_args: bool,
+}
+
+/// Render a command as a minijinja template.
+///
+/// The following variables are available in the template:
+/// - `args`: the passthrough arguments
+/// - `pwd`: the directory taco is running in
+/// - `project_name`: the name of the directory taco is running in
+/// - `env`: the environment variables
+///
+/// Together with the `vars` of the project, which take precedence over the variables above except
+/// for `args`. Variables that are not defined anywhere are an error, instead of silently rendering
+/// as nothing.
+pub fn render(
+    name: &str,
+    command: &str,
+    arguments: &[String],
+    pwd: &str,
+    vars: &BTreeMap<String, String>,
+) -> Result<Rendered> {
+    let mut env = Environment::new();
+    env.add_template(name, command)
+        .map_err(|e| render_error(name, command, e))?;
+    let template = env
+        .get_template(name)
+        .map_err(|e| render_error(name, command, e))?;
+
+    let project_name = Path::new(pwd)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let env_vars: BTreeMap<String, String> = std::env::vars().collect();
+    let builtins = ["args", "pwd", "project_name", "env"];
+
+    let undeclared = template.undeclared_variables(false);
+    if let Some(undefined) = undeclared.iter().find(|variable| {
+        !builtins.contains(&variable.as_str())
+            && !vars.contains_key(*variable)
+            && !env.globals().any(|(global, _)| global == *variable)
+    }) {
+        return Err(eyre!(
+            "Failed to render command \"{}\": \"{}\" is not defined in the vars of the project.",
+            name,
+            undefined
+        ));
+    }
+
+    let rendered = template
+        .render(context! {
+            args => arguments.to_vec(),
+            ..context! {
+                ..Value::from(vars.clone()),
+                ..context! {
+                    pwd => pwd,
+                    project_name => project_name,
+                    env => env_vars,
+                }
+            }
+        })
+        .map_err(|e| render_error(name, command, e))?;
+
+    Ok(Rendered {
+        command: rendered,
+        uses_args: undeclared.contains("args"),
+    })
+}
+
+fn render_error(name: &str, command: &str, error: minijinja::Error) -> color_eyre::Report {
+    let reason = match error.detail() {
+        Some(detail) => format!("{}: {}", error.kind(), detail),
+        None => error.kind().to_string(),
+    };
+
+    match error.line() {
+        Some(line) => eyre!(
+            "Failed to render command \"{}\" at line {}: {}\n  {}",
+            name,
+            line,
+            reason,
+            command.lines().nth(line - 1).unwrap_or_default()
+        ),
+        None => eyre!("Failed to render command \"{}\": {}", name, reason),
+    }
+}
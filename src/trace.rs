@@ -0,0 +1,81 @@
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The environment variable with the file to trace the spawned processes to.
+pub const VARIABLE: &str = "TACO_TRACE";
+
+/// A single process that taco spawned, written as one line of JSON.
+#[derive(Serialize)]
+struct Entry {
+    /// When the process finished (or was started, for background processes), in seconds since the
+    /// unix epoch.
+    timestamp: u64,
+    argv: Vec<String>,
+    cwd: String,
+
+    /// The environment variables that differ from the ones of taco itself, removed variables are
+    /// `null`.
+    env: BTreeMap<String, Option<String>>,
+
+    /// The exit code, `null` when the process is still running in the background or was killed
+    /// by a signal.
+    exit_code: Option<i32>,
+}
+
+/// Append the process to the trace log, when tracing is enabled with `TACO_TRACE`.
+pub fn log(cmd: &Command, exit_code: Option<i32>) {
+    let Some(path) = env::var_os(VARIABLE).filter(|path| !path.is_empty()) else {
+        return;
+    };
+
+    // The process already ran, so a broken trace log shouldn't fail the command
+    if let Err(error) = append(Path::new(&path), cmd, exit_code) {
+        eprintln!(
+            "Failed to write the trace log {}: {}",
+            Path::new(&path).display(),
+            error
+        );
+    }
+}
+
+fn append(path: &Path, cmd: &Command, exit_code: Option<i32>) -> Result<()> {
+    let argv = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect();
+
+    let cwd = match cmd.get_current_dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => env::current_dir()?,
+    };
+
+    let env = cmd
+        .get_envs()
+        .map(|(key, value)| {
+            (
+                key.to_string_lossy().to_string(),
+                value.map(|value| value.to_string_lossy().to_string()),
+            )
+        })
+        .collect();
+
+    let entry = Entry {
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        argv,
+        cwd: cwd.display().to_string(),
+        env,
+        exit_code,
+    };
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+
+    Ok(())
+}